@@ -0,0 +1,505 @@
+//! Write locks ([rfc4918 6](http://www.webdav.org/specs/rfc4918.html#locking)), which clients
+//! take before changing a resource so they don't overwrite each other's changes.
+//!
+//! Locks are only kept in memory, so they're all released when the server restarts.
+
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use axum::body::Bytes;
+use uuid::Uuid;
+use webdav::{
+    headers::Timeout,
+    xml::{
+        nonempty::NonEmpty, Element, Error, FromXml, Value, ValueMap, DAV_NAMESPACE, DAV_PREFIX,
+    },
+};
+
+use crate::web::encode;
+
+/// How long a lock lasts when the client doesn't ask for a timeout.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// The longest a lock lasts without being refreshed, including ones asked to last forever.
+const MAX_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The scheme lock tokens are URIs in.
+const TOKEN_SCHEME: &str = "urn:uuid:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Nobody else can lock the resource.
+    Exclusive,
+    /// Others can lock the resource too, as long as they share it.
+    Shared,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lock {
+    pub token: Uuid,
+    /// The path under `/dav` that was locked.
+    pub root: PathBuf,
+    pub scope: Scope,
+    /// Whether everything in `root` is locked along with it, for folders.
+    pub deep: bool,
+    /// Whoever took the lock, as they described themselves.
+    pub owner: Option<Value>,
+    expires: Instant,
+}
+
+impl Lock {
+    /// The lock token, as the URI clients send it back as.
+    pub fn token_uri(&self) -> String {
+        format!("{TOKEN_SCHEME}{}", self.token)
+    }
+
+    /// Whether the lock applies to `path`.
+    pub fn covers(&self, path: &Path) -> bool {
+        let path = normalize(path);
+        path == self.root || (self.deep && path.starts_with(&self.root))
+    }
+
+    /// Whether the lock and a lock on `root` can't both be held, with either being `deep`.
+    fn conflicts(&self, root: &Path, scope: Scope, deep: bool) -> bool {
+        let overlaps = self.covers(root) || (deep && self.root.starts_with(root));
+        overlaps && (self.scope == Scope::Exclusive || scope == Scope::Exclusive)
+    }
+
+    /// The `<activelock>` describing the lock.
+    fn active(&self) -> Value {
+        let mut lock = ValueMap::new();
+        lock.insert::<xml::LockType>(write());
+        lock.insert::<xml::LockScope>(scope(self.scope));
+        lock.insert::<xml::Depth>(Value::Text(if self.deep { "infinity" } else { "0" }.into()));
+        if let Some(owner) = &self.owner {
+            let mut owner = owner.clone();
+            super::props::prefix_namespaces(&mut owner);
+            lock.insert::<xml::Owner>(owner);
+        }
+
+        let remaining = self.expires.saturating_duration_since(Instant::now());
+        lock.insert::<xml::Timeout>(Value::Text(
+            format!("Second-{}", remaining.as_secs()).into(),
+        ));
+        lock.insert::<xml::LockToken>(href(self.token_uri()));
+        lock.insert::<xml::LockRoot>(href(format!("/dav/{}", encode(&self.root))));
+
+        Value::Map(lock)
+    }
+}
+
+/// Every lock currently held.
+#[derive(Debug, Default)]
+pub struct Locks(Mutex<HashMap<Uuid, Lock>>);
+
+impl Locks {
+    /// The locks held, less any that expired.
+    fn held(&self) -> MutexGuard<'_, HashMap<Uuid, Lock>> {
+        let mut locks = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        locks.retain(|_, lock| lock.expires > now);
+        locks
+    }
+
+    /// Lock `root`, or return the root of the lock it conflicts with: one on `root` or a
+    /// folder it's in or, for `deep` locks, on anything in it.
+    pub fn lock(
+        &self,
+        root: &Path,
+        scope: Scope,
+        deep: bool,
+        owner: Option<Value>,
+        timeout: Option<Timeout>,
+    ) -> Result<Lock, PathBuf> {
+        let mut locks = self.held();
+        let root = normalize(root);
+
+        if let Some(conflict) = locks.values().find(|l| l.conflicts(&root, scope, deep)) {
+            return Err(conflict.root.clone());
+        }
+
+        let lock = Lock {
+            token: Uuid::new_v4(),
+            root,
+            scope,
+            deep,
+            owner,
+            expires: Instant::now() + duration(timeout),
+        };
+        locks.insert(lock.token, lock.clone());
+
+        Ok(lock)
+    }
+
+    /// Extend the lock `token` on `path` by `timeout`, if it's still held.
+    pub fn refresh(&self, path: &Path, token: &Uuid, timeout: Option<Timeout>) -> Option<Lock> {
+        let mut locks = self.held();
+        let lock = locks.get_mut(token).filter(|lock| lock.covers(path))?;
+        lock.expires = Instant::now() + duration(timeout);
+
+        Some(lock.clone())
+    }
+
+    /// Release the lock `token` on `path`, returning whether it was held.
+    pub fn unlock(&self, path: &Path, token: &Uuid) -> bool {
+        let mut locks = self.held();
+        match locks.get(token) {
+            Some(lock) if lock.covers(path) => locks.remove(token).is_some(),
+            _ => false,
+        }
+    }
+
+    /// Release the locks on `path` and anything in it, once it's gone.
+    pub fn release(&self, path: &Path) {
+        let path = normalize(path);
+        self.held().retain(|_, lock| !lock.root.starts_with(&path));
+    }
+
+    /// The locks that apply to `path`.
+    pub fn on(&self, path: &Path) -> Vec<Lock> {
+        self.held()
+            .values()
+            .filter(|lock| lock.covers(path))
+            .cloned()
+            .collect()
+    }
+
+    /// A lock on `path`, or on anything in it if `deep`, whose token isn't one of `tokens`.
+    pub fn unheld(&self, path: &Path, deep: bool, tokens: &[Uuid]) -> Option<Lock> {
+        let path = normalize(path);
+
+        self.held()
+            .values()
+            .filter(|lock| lock.covers(&path) || (deep && lock.root.starts_with(&path)))
+            .find(|lock| !tokens.contains(&lock.token))
+            .cloned()
+    }
+}
+
+/// How long a lock asked to last `timeout` lasts.
+fn duration(timeout: Option<Timeout>) -> Duration {
+    match timeout {
+        Some(Timeout::Seconds(seconds)) => Duration::from_secs(seconds.into()).min(MAX_TIMEOUT),
+        Some(Timeout::Infinite) => MAX_TIMEOUT,
+        None => DEFAULT_TIMEOUT,
+    }
+}
+
+/// `path` without leading slashes or `.`, so the same resource always has the same path.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect()
+}
+
+/// The lock token in a URI like `urn:uuid:…`.
+pub fn token(uri: &str) -> Option<Uuid> {
+    uri.strip_prefix(TOKEN_SCHEME)?.parse().ok()
+}
+
+/// The scope and owner asked for in a `<lockinfo>` body.
+pub fn parse_lockinfo(body: Bytes) -> Option<(Scope, Option<Value>)> {
+    let xml::LockInfo { scope, owner } = xml::LockInfo::from_xml(body)
+        .inspect_err(|err| tracing::debug!("invalid lockinfo: {err}"))
+        .ok()?;
+
+    Some((scope, owner))
+}
+
+/// The `lockdiscovery` property, listing the active `locks` on a resource.
+pub struct LockDiscovery(pub Vec<Lock>);
+
+impl Element for LockDiscovery {
+    const NAMESPACE: &'static str = DAV_NAMESPACE;
+    const PREFIX: &'static str = DAV_PREFIX;
+    const LOCAL_NAME: &'static str = "lockdiscovery";
+}
+
+impl From<LockDiscovery> for Value {
+    fn from(LockDiscovery(locks): LockDiscovery) -> Value {
+        let Some(locks) = NonEmpty::from_vec(locks.iter().map(Lock::active).collect()) else {
+            return Value::Empty;
+        };
+
+        let mut discovery = ValueMap::new();
+        discovery.insert::<xml::ActiveLock>(match locks.len() {
+            1 => locks.head,
+            _ => Value::List(Box::new(locks)),
+        });
+
+        Value::Map(discovery)
+    }
+}
+
+/// The `supportedlock` property: exclusive and shared write locks.
+pub struct SupportedLock;
+
+impl Element for SupportedLock {
+    const NAMESPACE: &'static str = DAV_NAMESPACE;
+    const PREFIX: &'static str = DAV_PREFIX;
+    const LOCAL_NAME: &'static str = "supportedlock";
+}
+
+impl From<SupportedLock> for Value {
+    fn from(_: SupportedLock) -> Value {
+        let entry = |s| {
+            let mut entry = ValueMap::new();
+            entry.insert::<xml::LockScope>(scope(s));
+            entry.insert::<xml::LockType>(write());
+            Value::Map(entry)
+        };
+
+        let mut supported = ValueMap::new();
+        supported.insert::<xml::LockEntry>(Value::List(Box::new(NonEmpty::from((
+            entry(Scope::Exclusive),
+            vec![entry(Scope::Shared)],
+        )))));
+
+        Value::Map(supported)
+    }
+}
+
+/// An `<href>` to `uri`.
+fn href(uri: String) -> Value {
+    let mut href = ValueMap::new();
+    href.insert::<webdav::xml::elements::Href>(Value::Text(uri.into()));
+    Value::Map(href)
+}
+
+/// A `<locktype>` of `<write>`, the only type of lock there is.
+fn write() -> Value {
+    let mut write = ValueMap::new();
+    write.insert::<xml::Write>(Value::Empty);
+    Value::Map(write)
+}
+
+/// A `<lockscope>` of `scope`.
+fn scope(scope: Scope) -> Value {
+    let mut value = ValueMap::new();
+    match scope {
+        Scope::Exclusive => value.insert::<xml::Exclusive>(Value::Empty),
+        Scope::Shared => value.insert::<xml::Shared>(Value::Empty),
+    }
+    Value::Map(value)
+}
+
+/// The elements locks are described with, which `webdav` doesn't have types for.
+mod xml {
+    use super::*;
+
+    /// Declare elements in the `DAV:` namespace, which only need their names.
+    macro_rules! elements {
+        ($($name:ident = $tag:literal,)*) => {$(
+            pub struct $name;
+
+            impl Element for $name {
+                const NAMESPACE: &'static str = DAV_NAMESPACE;
+                const PREFIX: &'static str = DAV_PREFIX;
+                const LOCAL_NAME: &'static str = $tag;
+            }
+
+            impl TryFrom<&Value> for $name {
+                type Error = Error;
+
+                fn try_from(_: &Value) -> Result<Self, Error> {
+                    Ok(Self)
+                }
+            }
+        )*};
+    }
+
+    elements! {
+        ActiveLock = "activelock",
+        Depth = "depth",
+        Exclusive = "exclusive",
+        LockEntry = "lockentry",
+        LockRoot = "lockroot",
+        LockToken = "locktoken",
+        Shared = "shared",
+        Timeout = "timeout",
+        Write = "write",
+    }
+
+    /// `<locktype>`, which has to be `<write>`, the only type of lock there is.
+    pub struct LockType;
+
+    impl Element for LockType {
+        const NAMESPACE: &'static str = DAV_NAMESPACE;
+        const PREFIX: &'static str = DAV_PREFIX;
+        const LOCAL_NAME: &'static str = "locktype";
+    }
+
+    impl TryFrom<&Value> for LockType {
+        type Error = Error;
+
+        fn try_from(value: &Value) -> Result<Self, Error> {
+            value
+                .to_map()?
+                .get::<Write>()
+                .ok_or(Error::MissingElement("write"))??;
+            Ok(Self)
+        }
+    }
+
+    /// `<lockscope>`, when read from a request.
+    pub struct LockScope(pub Scope);
+
+    impl Element for LockScope {
+        const NAMESPACE: &'static str = DAV_NAMESPACE;
+        const PREFIX: &'static str = DAV_PREFIX;
+        const LOCAL_NAME: &'static str = "lockscope";
+    }
+
+    impl TryFrom<&Value> for LockScope {
+        type Error = Error;
+
+        fn try_from(value: &Value) -> Result<Self, Error> {
+            let scope = value.to_map()?;
+            match (scope.get::<Exclusive>(), scope.get::<Shared>()) {
+                (Some(_), None) => Ok(Self(Scope::Exclusive)),
+                (None, Some(_)) => Ok(Self(Scope::Shared)),
+                (Some(_), Some(_)) => Err(Error::ConflictingElements("exclusive and shared")),
+                (None, None) => Err(Error::MissingElement("exclusive")),
+            }
+        }
+    }
+
+    /// `<owner>`, which can be any XML.
+    pub struct Owner(pub Value);
+
+    impl Element for Owner {
+        const NAMESPACE: &'static str = DAV_NAMESPACE;
+        const PREFIX: &'static str = DAV_PREFIX;
+        const LOCAL_NAME: &'static str = "owner";
+    }
+
+    impl TryFrom<&Value> for Owner {
+        type Error = Error;
+
+        fn try_from(value: &Value) -> Result<Self, Error> {
+            Ok(Self(value.clone()))
+        }
+    }
+
+    /// The `<lockinfo>` body of a request for a new lock.
+    pub struct LockInfo {
+        pub scope: Scope,
+        pub owner: Option<Value>,
+    }
+
+    impl Element for LockInfo {
+        const NAMESPACE: &'static str = DAV_NAMESPACE;
+        const PREFIX: &'static str = DAV_PREFIX;
+        const LOCAL_NAME: &'static str = "lockinfo";
+    }
+
+    impl TryFrom<&Value> for LockInfo {
+        type Error = Error;
+
+        fn try_from(value: &Value) -> Result<Self, Error> {
+            let info = value.to_map()?;
+
+            info.get::<LockType>()
+                .ok_or(Error::MissingElement("locktype"))??;
+
+            Ok(Self {
+                scope: info
+                    .get::<LockScope>()
+                    .ok_or(Error::MissingElement("lockscope"))??
+                    .0,
+                owner: info.get::<Owner>().transpose()?.map(|Owner(owner)| owner),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conflicting_locks() {
+        let locks = Locks::default();
+
+        let folder = locks
+            .lock(Path::new("/A"), Scope::Shared, true, None, None)
+            .unwrap();
+        assert!(locks
+            .lock(Path::new("A"), Scope::Shared, false, None, None)
+            .is_ok());
+        let conflict = locks
+            .lock(
+                Path::new("A/Notes.pdf"),
+                Scope::Exclusive,
+                false,
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(conflict, Path::new("A"));
+        assert!(locks
+            .lock(Path::new(""), Scope::Exclusive, true, None, None)
+            .is_err());
+        assert!(locks
+            .lock(Path::new(""), Scope::Exclusive, false, None, None)
+            .is_ok());
+
+        let unheld = locks.unheld(Path::new("A/Notes.pdf"), false, &[folder.token]);
+        assert!(unheld.is_none());
+        assert!(locks.unheld(Path::new("A/Notes.pdf"), false, &[]).is_some());
+
+        assert!(!locks.unlock(Path::new("B"), &folder.token));
+        assert!(locks.unlock(Path::new("A/Notes.pdf"), &folder.token));
+        assert!(locks
+            .lock(
+                Path::new("A/Notes.pdf"),
+                Scope::Exclusive,
+                false,
+                None,
+                None
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn locks_expire() {
+        let locks = Locks::default();
+        let lock = locks
+            .lock(
+                Path::new("A"),
+                Scope::Exclusive,
+                false,
+                None,
+                Some(Timeout::Seconds(0)),
+            )
+            .unwrap();
+
+        assert!(locks.on(Path::new("A")).is_empty());
+        assert!(locks.refresh(Path::new("A"), &lock.token, None).is_none());
+    }
+
+    #[test]
+    fn parses_lockinfo() {
+        let body = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:lockinfo xmlns:D="DAV:">
+  <D:lockscope><D:exclusive/></D:lockscope>
+  <D:locktype><D:write/></D:locktype>
+  <D:owner><D:href>mailto:someone@example.com</D:href></D:owner>
+</D:lockinfo>"#;
+
+        let (scope, owner) = parse_lockinfo(Bytes::from_static(body.as_bytes())).unwrap();
+        assert_eq!(scope, Scope::Exclusive);
+        assert!(matches!(owner, Some(Value::Map(_))));
+
+        let shared = r#"<lockinfo xmlns="DAV:"><lockscope><shared/></lockscope><locktype><write/></locktype></lockinfo>"#;
+        let (scope, owner) = parse_lockinfo(Bytes::from_static(shared.as_bytes())).unwrap();
+        assert_eq!((scope, owner), (Scope::Shared, None));
+
+        let unscoped = r#"<lockinfo xmlns="DAV:"><locktype><write/></locktype></lockinfo>"#;
+        assert!(parse_lockinfo(Bytes::from_static(unscoped.as_bytes())).is_none());
+    }
+}
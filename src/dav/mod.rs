@@ -0,0 +1,2387 @@
+//! Implementation of WebDAV ([rfc4918](http://www.webdav.org/specs/rfc4918.html)) over [`Filesystem`](crate::rm::Filesystem).
+
+use std::{
+    path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    remarkable::{
+        names, Element, Format, Parent, Remarkable, Upload, LOST_DIRECTORY, PINNED_DIRECTORY,
+        TRASH_DIRECTORY,
+    },
+    render::{pdf, png, svg},
+    web::{decode, encode},
+};
+use axum::{
+    body::{self, Bytes},
+    extract::{self, Query, Request, State},
+    http::{header, HeaderMap, HeaderName, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing, Extension, Router,
+};
+use color_eyre::eyre;
+use dashmap::{DashMap, DashSet};
+use futures::{future, StreamExt};
+use headers::{ContentLength, ETag, HeaderMapExt, LastModified};
+use headers_core::HeaderValue;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use uuid::Uuid;
+use webdav::{
+    headers::{
+        Condition, Depth, Destination, If, LockToken, Overwrite, ResourceTag, Timeout, LOCK_TOKEN,
+    },
+    methods::{COPY, LOCK, MKCOL, MOVE, PROPFIND, PROPPATCH, UNLOCK},
+    xml::{
+        elements::{self, Href, Multistatus, Properties, Propfind, Propstat, Status},
+        nonempty::NonEmpty,
+        properties, FromXml, IntoXml, Value, ValueMap,
+    },
+};
+
+mod lock;
+mod props;
+
+/// Settings and state shared by every WebDAV request.
+#[derive(Debug, Default)]
+pub struct Dav {
+    /// Whether to answer PROPFINDs with `Depth: infinity`, which walk the whole tablet at once.
+    infinite_depth: bool,
+    /// The size of each export of a document and the modification time it was measured at,
+    /// so listing a folder doesn't export every document in it each time.
+    export_sizes: DashMap<(Uuid, Export), (SystemTime, u64)>,
+    locks: lock::Locks,
+    /// Documents uploaded since the server started, which can be uploaded again until they're
+    /// opened on the tablet.
+    uploaded: DashSet<Uuid>,
+    /// Paths empty files were uploaded to, as Finder and Explorer do before locking them and
+    /// uploading what's in them. Nothing is imported until then.
+    placeholders: DashSet<path::PathBuf>,
+}
+
+impl Dav {
+    pub fn new(infinite_depth: bool) -> Self {
+        Self {
+            infinite_depth,
+            ..Default::default()
+        }
+    }
+}
+
+impl Dav {
+    /// The size of the document `uuid` exported as `export`, only exporting it again if it
+    /// changed since it was last measured.
+    async fn export_size(
+        &self,
+        fs: &Remarkable,
+        uuid: &Uuid,
+        element: &Element,
+        export: Export,
+    ) -> eyre::Result<u64> {
+        let modified = element.last_modified();
+        let cached = self
+            .export_sizes
+            .get(&(*uuid, export))
+            .filter(|entry| entry.0 == modified)
+            .map(|entry| entry.1);
+        if let Some(size) = cached {
+            return Ok(size);
+        }
+
+        let size = export_document(fs, uuid, element, export).await?.len() as u64;
+        self.export_sizes.insert((*uuid, export), (modified, size));
+
+        Ok(size)
+    }
+}
+
+pub fn router(dav: Dav) -> Router<Arc<Remarkable>> {
+    Router::new()
+        .route("/dav", routing::any(handler))
+        .route("/dav/", routing::any(handler))
+        .route("/dav/*path", routing::any(handler))
+        .layer(Extension(Arc::new(dav)))
+}
+
+pub async fn handler(
+    method: Method,
+    path: Option<extract::Path<path::PathBuf>>,
+    State(fs): State<Arc<Remarkable>>,
+    Extension(dav): Extension<Arc<Dav>>,
+    req: Request,
+) -> Response {
+    let path = match path {
+        Some(extract::Path(path)) => path,
+        None => path::Path::new("/").into(),
+    };
+
+    if let Some(resp) = check_preconditions(&fs, &dav, &method, &path, req.headers()) {
+        return with_dav_header(resp);
+    }
+    // found before the request moves or deletes what it's on
+    let locked = lock_path(&fs, &path);
+
+    let resp = match method {
+        Method::GET => dav_get(req, path.clone(), fs).await,
+        Method::PUT => dav_put(req, path.clone(), fs, dav.clone()).await,
+        Method::DELETE if dav.placeholders.remove(&placeholder(&path)).is_some() => {
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Method::DELETE => dav_delete(req, path.clone(), fs).await,
+        Method::OPTIONS => dav_options(),
+        _ if method == COPY.as_ref() => dav_copy(req, path.clone(), fs).await,
+        _ if method == MOVE.as_ref() => dav_move(req, path.clone(), fs).await,
+        _ if method == MKCOL.as_ref() => dav_mkcol(req, path.clone(), fs).await,
+        _ if method == LOCK.as_ref() => dav_lock(req, path.clone(), fs, dav.clone()).await,
+        _ if method == UNLOCK.as_ref() => dav_unlock(req, path.clone(), fs, dav.clone()).await,
+        _ if method == PROPFIND.as_ref() => dav_propfind(req, path.clone(), fs, dav.clone()).await,
+        _ if method == PROPPATCH.as_ref() => dav_proppatch(req, path.clone(), fs).await,
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    };
+
+    // locks go away along with what they're on
+    if resp.status().is_success() && (method == Method::DELETE || method == MOVE.as_ref()) {
+        dav.locks.release(&locked);
+    }
+
+    with_dav_header(resp)
+}
+
+/// Advertise the WebDAV compliance classes supported: 1 for the basics, 2 for locking.
+fn with_dav_header(mut resp: Response) -> Response {
+    resp.headers_mut()
+        .append("dav", HeaderValue::from_static("1, 2"));
+
+    resp
+}
+
+async fn dav_get(req: Request, path: path::PathBuf, fs: Arc<Remarkable>) -> Response {
+    if let Some((document, index)) = page_path(&path, "svg") {
+        return match fs.read_page(document, index).await {
+            Ok(page) => (
+                [(header::CONTENT_TYPE, svg::CONTENT_TYPE)],
+                svg::render(&page),
+            )
+                .into_response(),
+            Err(err) => {
+                tracing::debug!("no page at {path:?}: {err}");
+                StatusCode::NOT_FOUND.into_response()
+            }
+        };
+    }
+
+    if let Some((document, index)) = page_path(&path, "png") {
+        let Ok(Query(query)) = Query::<RenderQuery>::try_from_uri(req.uri()) else {
+            return StatusCode::BAD_REQUEST.into_response();
+        };
+
+        let page = match fs.read_page(document, index).await {
+            Ok(page) => page,
+            Err(err) => {
+                tracing::debug!("no page at {path:?}: {err}");
+                return StatusCode::NOT_FOUND.into_response();
+            }
+        };
+
+        // drawing takes a while at large scales, so it's kept off the executor
+        let scale = query.scale();
+        return match tokio::task::spawn_blocking(move || png::render(&page, scale)).await {
+            Ok(Ok(png)) => ([(header::CONTENT_TYPE, png::CONTENT_TYPE)], png).into_response(),
+            Ok(Err(err)) => {
+                tracing::debug!("can't render {path:?}: {err}");
+                StatusCode::BAD_REQUEST.into_response()
+            }
+            Err(err) => {
+                tracing::error!("failed to render {path:?}: {err}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        };
+    }
+
+    if let Some((uuid, element, export)) = export(&fs, &path) {
+        return match export_document(&fs, &uuid, &element, export).await {
+            Ok(bytes) => {
+                let mut headers = HeaderMap::new();
+                headers.insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static(export.content_type()),
+                );
+                headers.typed_insert(ContentLength(bytes.len() as u64));
+                headers.typed_insert(LastModified::from(element.last_modified()));
+                if let Ok(etag) = etag(&uuid, &element, Some(export)).parse::<ETag>() {
+                    headers.typed_insert(etag);
+                }
+
+                (headers, bytes).into_response()
+            }
+            Err(err) => {
+                tracing::error!("failed to export {path:?}: {err}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        };
+    }
+
+    // folders have nothing to download, what's in them is listed by PROPFIND
+    match Resource::at(&fs, &path) {
+        Some(_) => (
+            [(header::ALLOW, COLLECTION_METHODS)],
+            StatusCode::METHOD_NOT_ALLOWED,
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Options for rendering raster pages, like `1.png?dpi=300` or `1.png?scale=0.5`.
+#[derive(serde::Deserialize, Default)]
+struct RenderQuery {
+    dpi: Option<f32>,
+    scale: Option<f32>,
+}
+
+impl RenderQuery {
+    fn scale(&self) -> f32 {
+        match (self.scale, self.dpi) {
+            (Some(scale), _) => scale,
+            (None, Some(dpi)) => png::scale_for_dpi(dpi),
+            (None, None) => 1.0,
+        }
+    }
+}
+
+/// Split a path to a rendered page like `Notes/Tester/1.svg` into the document path and page index.
+fn page_path<'a>(path: &'a path::Path, extension: &str) -> Option<(&'a path::Path, usize)> {
+    if path.extension()? != extension {
+        return None;
+    }
+
+    let number: usize = path.file_stem()?.to_str()?.parse().ok()?;
+
+    Some((path.parent()?, number.checked_sub(1)?))
+}
+
+const EPUB_CONTENT_TYPE: &str = "application/epub+zip";
+
+/// The ways a document can be downloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Export {
+    /// As a PDF with its annotations, which is how notebooks and PDFs are served by default.
+    Pdf,
+    /// As the EPUB it was imported from, which is how EPUBs are served by default.
+    Epub,
+}
+
+impl Export {
+    /// How documents in `format` are served by default.
+    fn default_for(format: Format) -> Self {
+        match format {
+            Format::Notebook | Format::Pdf => Self::Pdf,
+            Format::Epub => Self::Epub,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Pdf => pdf::CONTENT_TYPE,
+            Self::Epub => EPUB_CONTENT_TYPE,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Pdf => "pdf",
+            Self::Epub => "epub",
+        }
+    }
+}
+
+/// Find the document at `path` and how to export it, from the extension it's requested with.
+///
+/// `Notes` and `Notes.pdf` are the same notebook, while `Book.pdf` is the annotated PDF the
+/// tablet converted the EPUB `Book` into.
+fn export(fs: &Remarkable, path: &path::Path) -> Option<(Uuid, Arc<Element>, Export)> {
+    let (uuid, element, extension) = match fs.element(path) {
+        Some((uuid, element)) => (uuid, element, None),
+        None => {
+            let (uuid, element) = fs.element(path.with_extension(""))?;
+            (uuid, element, Some(path.extension()?.to_str()?))
+        }
+    };
+
+    let export = match (element.format()?, extension) {
+        (Format::Notebook | Format::Pdf, None | Some("pdf")) => Export::Pdf,
+        (Format::Epub, None | Some("epub")) => Export::Epub,
+        (Format::Epub, Some("pdf")) => Export::Pdf,
+        _ => return None,
+    };
+
+    Some((uuid, element, export))
+}
+
+/// Export the document `uuid` as `export`.
+async fn export_document(
+    fs: &Remarkable,
+    uuid: &Uuid,
+    element: &Element,
+    export: Export,
+) -> eyre::Result<Vec<u8>> {
+    match (export, element.format()) {
+        (Export::Epub, _) => fs.read_original(uuid).await,
+        (Export::Pdf, Some(Format::Notebook)) => pdf::render(&fs.read_pages(uuid).await?),
+        (Export::Pdf, _) => annotated_pdf(fs, uuid, element).await,
+    }
+}
+
+/// The PDF a document is displayed from with its annotations drawn on top, falling back to
+/// the unannotated PDF if they can't be merged.
+async fn annotated_pdf(fs: &Remarkable, uuid: &Uuid, element: &Element) -> eyre::Result<Vec<u8>> {
+    let original = fs.read_pdf(uuid).await?;
+
+    let redirects = element.pages()?.iter().map(|p| p.redirect);
+    let pages: Vec<_> = redirects.zip(fs.read_pages(uuid).await?).collect();
+
+    // without any pages listed, the tablet shows the original as is
+    if pages.is_empty() {
+        return Ok(original);
+    }
+
+    match pdf::annotate(&original, &pages) {
+        Ok(annotated) => Ok(annotated),
+        Err(err) => {
+            tracing::warn!(
+                "can't annotate {:?}, serving the original: {err}",
+                element.name()
+            );
+            Ok(original)
+        }
+    }
+}
+
+/// The largest file that can be uploaded.
+const MAX_UPLOAD: u64 = 1 << 30;
+
+async fn dav_put(
+    req: Request,
+    path: path::PathBuf,
+    fs: Arc<Remarkable>,
+    dav: Arc<Dav>,
+) -> Response {
+    let format = match path.extension().and_then(|e| e.to_str()) {
+        Some("pdf") => Format::Pdf,
+        Some("epub") => Format::Epub,
+        _ => return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response(),
+    };
+
+    let (Some(name), Some(parent)) = (path.file_stem().and_then(|s| s.to_str()), path.parent())
+    else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let Some(parent) = fs.parent_at(parent) else {
+        return StatusCode::CONFLICT.into_response();
+    };
+
+    // documents on the tablet aren't replaced, as that would misplace their annotations, but
+    // ones uploaded here can be until they're opened
+    let replaced = match Resource::at(&fs, &path) {
+        None => None,
+        Some(Resource {
+            element: Some((uuid, element)),
+            export: Some(_),
+            ..
+        }) if dav.uploaded.contains(&uuid) && element.last_opened().is_none() => {
+            Some((uuid, element))
+        }
+        Some(_) => {
+            tracing::debug!("not overwriting {path:?}");
+            return StatusCode::METHOD_NOT_ALLOWED.into_response();
+        }
+    };
+
+    let upload = fs.new_upload();
+    let size = match receive(req.into_body(), &upload).await {
+        Ok(size) => size,
+        Err(err) => {
+            tracing::debug!("failed to read upload of {path:?}: {err}");
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+
+    if size == 0 && replaced.is_none() {
+        dav.placeholders.insert(placeholder(&path));
+        return StatusCode::CREATED.into_response();
+    }
+
+    let page_count = match check_upload(format, &upload).await {
+        Ok(page_count) => page_count,
+        Err(err) => {
+            tracing::debug!("{path:?} isn't a valid {format:?}: {err}");
+            return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
+        }
+    };
+
+    // what's replaced keeps its name, rather than what tells it apart from its siblings
+    let name = match &replaced {
+        Some((_, element)) => element.name().to_string(),
+        None => names::decode(name),
+    };
+    let uuid = match fs
+        .import_upload(parent, &name, format, upload, page_count)
+        .await
+    {
+        Ok(uuid) => uuid,
+        Err(err) => {
+            tracing::error!("failed to import {path:?}: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    tracing::info!("imported {path:?} as {uuid}");
+    dav.placeholders.remove(&placeholder(&path));
+    dav.uploaded.insert(uuid);
+
+    let Some((replaced, _)) = replaced else {
+        return StatusCode::CREATED.into_response();
+    };
+
+    // it was only ever uploaded here, so it doesn't go to the trash
+    dav.uploaded.remove(&replaced);
+    match fs.purge(replaced).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => {
+            tracing::error!("failed to remove what {path:?} replaced: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Write the body of an upload to `upload` as it comes in, rather than keeping it in memory.
+/// Returns its size.
+async fn receive(body: body::Body, upload: &Upload) -> eyre::Result<u64> {
+    let mut file = tokio::fs::File::create(upload.path()).await?;
+    let mut body = body.into_data_stream();
+    let mut size = 0;
+
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        size += chunk.len() as u64;
+        if size > MAX_UPLOAD {
+            return Err(eyre::eyre!("larger than {MAX_UPLOAD} bytes"));
+        }
+
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+
+    Ok(size)
+}
+
+/// Check `upload` holds a document in `format`, returning its page count if it's known.
+async fn check_upload(format: Format, upload: &Upload) -> eyre::Result<Option<u32>> {
+    match format {
+        Format::Pdf => Ok(Some(pdf::page_count(
+            &tokio::fs::read(upload.path()).await?,
+        )?)),
+        // EPUBs are zip files, and are paginated by the tablet
+        _ => {
+            let mut magic = [0; 4];
+            let mut file = tokio::fs::File::open(upload.path()).await?;
+            match file.read_exact(&mut magic).await {
+                Ok(_) if magic == *b"PK\x03\x04" => Ok(None),
+                _ => Err(eyre::eyre!("not a zip file")),
+            }
+        }
+    }
+}
+
+/// How `path` is kept among [`Dav::placeholders`].
+fn placeholder(path: &path::Path) -> path::PathBuf {
+    path.strip_prefix("/").unwrap_or(path).to_path_buf()
+}
+
+async fn dav_delete(_req: Request, path: path::PathBuf, fs: Arc<Remarkable>) -> Response {
+    let uuid = match Resource::at(&fs, &path) {
+        Some(Resource {
+            element: Some((uuid, _)),
+            ..
+        }) => uuid,
+        // the root can't be deleted
+        Some(_) => return StatusCode::FORBIDDEN.into_response(),
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    // deleting from the favorites only unpins
+    let deleted = match in_favorites(&path) {
+        true => fs.set_pinned(uuid, false).await,
+        false => delete(&fs, uuid).await,
+    };
+
+    match deleted {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => {
+            tracing::error!("failed to delete {path:?}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Whether `path` is directly in the favorites, which only list elements kept elsewhere.
+fn in_favorites(path: &path::Path) -> bool {
+    let path = path.strip_prefix("/").unwrap_or(path);
+    path.parent() == Some(path::Path::new(PINNED_DIRECTORY))
+}
+
+/// The folders at the root that only list elements kept elsewhere, Lost+Found only while
+/// something's in it.
+fn virtual_folders(fs: &Remarkable) -> impl Iterator<Item = &'static str> {
+    [PINNED_DIRECTORY, TRASH_DIRECTORY]
+        .into_iter()
+        .chain(fs.has_lost().then_some(LOST_DIRECTORY))
+}
+
+/// Delete the element `uuid`, which is recoverable from the trash unless it's deleted from there.
+async fn delete(fs: &Remarkable, uuid: Uuid) -> eyre::Result<()> {
+    match fs.is_trashed(&uuid) {
+        true => fs.purge(uuid).await,
+        false => fs.move_to_trash(uuid).await,
+    }
+}
+
+/// The methods WebDAV clients can use, given to OPTIONS so they know they can write.
+const ALLOWED_METHODS: &str =
+    "OPTIONS, GET, PUT, DELETE, COPY, MOVE, MKCOL, PROPFIND, PROPPATCH, LOCK, UNLOCK";
+
+/// The methods allowed on folders, which can't be read or written like documents.
+const COLLECTION_METHODS: &str =
+    "OPTIONS, DELETE, COPY, MOVE, MKCOL, PROPFIND, PROPPATCH, LOCK, UNLOCK";
+
+fn dav_options() -> Response {
+    (
+        [
+            (header::ALLOW, ALLOWED_METHODS),
+            // Windows only writes to servers that say they're authored through WebDAV
+            (HeaderName::from_static("ms-author-via"), "DAV"),
+        ],
+        (),
+    )
+        .into_response()
+}
+
+async fn dav_proppatch(req: Request, path: path::PathBuf, fs: Arc<Remarkable>) -> Response {
+    let Some(resource) = Resource::at(&fs, &path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(href) = resource.href() else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let update = match body::to_bytes(req.into_body(), MAX_XML_BODY).await {
+        Ok(body) => props::parse_propertyupdate(body),
+        Err(_) => None,
+    };
+    let Some(update) = update else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    // the root has nowhere to keep any properties
+    let Some((uuid, element)) = resource.element.clone() else {
+        let mut names = update.set;
+        names.as_mut().extend(update.remove.as_ref().clone());
+        return multistatus(vec![proppatch_response(
+            href,
+            vec![(names, StatusCode::FORBIDDEN)],
+        )]);
+    };
+
+    let mut dead = match props::read_dead(&fs, &uuid).await {
+        Ok(dead) => dead,
+        Err(err) => {
+            tracing::error!("failed to read the properties of {path:?}: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let dead_before = dead.clone();
+    let (mut name, mut pinned, mut tags) = (None, None, None);
+
+    // every change is checked before any is made, as they're either all made or none are
+    let (mut done, mut refused) = (ValueMap::new(), ValueMap::new());
+    let changes = (update.remove.as_ref().iter().map(|(name, _)| (name, None))).chain(
+        update
+            .set
+            .as_ref()
+            .iter()
+            .map(|(name, value)| (name, Some(value))),
+    );
+
+    for (prop, value) in changes {
+        let kind = props::Property::named(prop.namespace.as_deref(), &prop.local_name);
+        let single = || {
+            let mut single = ValueMap::new();
+            single
+                .as_mut()
+                .insert(prop.clone(), value.cloned().unwrap_or_default());
+            single
+        };
+
+        let allowed = match (kind, value) {
+            (props::Property::DisplayName, Some(value)) => match value.to_str() {
+                Ok(value) if !value.trim().is_empty() => {
+                    name = Some(value.to_string());
+                    true
+                }
+                _ => false,
+            },
+            (props::Property::Pinned, None) => {
+                pinned = Some(false);
+                true
+            }
+            (props::Property::Pinned, Some(_)) => match single().get::<props::Pinned>() {
+                Some(Ok(props::Pinned(value))) => {
+                    pinned = Some(value);
+                    true
+                }
+                _ => false,
+            },
+            (props::Property::Tags, _) if element.is_dir() => false,
+            (props::Property::Tags, None) => {
+                tags = Some(Vec::new());
+                true
+            }
+            (props::Property::Tags, Some(_)) => match single().get::<props::Tags>() {
+                Some(Ok(props::Tags(value))) => {
+                    tags = Some(value);
+                    true
+                }
+                _ => false,
+            },
+            (props::Property::Dead, None) => {
+                dead.as_mut().shift_remove(prop);
+                true
+            }
+            (props::Property::Dead, Some(value)) => {
+                dead.as_mut().insert(prop.clone(), value.clone());
+                true
+            }
+            (props::Property::DisplayName | props::Property::Protected, _) => false,
+        };
+
+        match allowed {
+            true => done.as_mut().insert(prop.clone(), Value::Empty),
+            false => refused.as_mut().insert(prop.clone(), Value::Empty),
+        };
+    }
+
+    if !refused.as_ref().is_empty() {
+        return multistatus(vec![proppatch_response(
+            href,
+            vec![
+                (refused, StatusCode::FORBIDDEN),
+                (done, StatusCode::FAILED_DEPENDENCY),
+            ],
+        )]);
+    }
+
+    let result = async {
+        if let Some(name) = name.filter(|name| name != element.name()) {
+            fs.rename(uuid, &name).await?;
+        }
+        if let Some(pinned) = pinned.filter(|pinned| *pinned != element.is_pinned()) {
+            fs.set_pinned(uuid, pinned).await?;
+        }
+        if let Some(tags) = tags {
+            fs.set_tags(uuid, &tags).await?;
+        }
+        if dead != dead_before {
+            props::write_dead(&fs, &uuid, dead).await?;
+        }
+        eyre::Ok(())
+    };
+
+    match result.await {
+        Ok(()) => multistatus(vec![proppatch_response(href, vec![(done, StatusCode::OK)])]),
+        Err(err) => {
+            tracing::error!("failed to set the properties of {path:?}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// The `<response>` to a PROPPATCH, with the properties that got each status.
+fn proppatch_response(href: Href, props: Vec<(ValueMap, StatusCode)>) -> elements::Response {
+    let propstats: Vec<_> = props
+        .into_iter()
+        .filter(|(props, _)| !props.as_ref().is_empty())
+        .map(|(props, status)| propstat(props, status))
+        .collect();
+
+    elements::Response::Propstat {
+        href,
+        propstat: NonEmpty::from_vec(propstats)
+            .unwrap_or_else(|| NonEmpty::new(propstat(ValueMap::new(), StatusCode::OK))),
+        responsedescription: None,
+    }
+}
+
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+
+/// The largest XML request body that's read.
+const MAX_XML_BODY: usize = 1 << 20;
+
+/// The reason for refusing `Depth: infinity`, from [rfc4918 9.1](http://www.webdav.org/specs/rfc4918.html#rfc.section.9.1).
+const FINITE_DEPTH_ERROR: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:error xmlns:d="DAV:"><d:propfind-finite-depth/></d:error>"#;
+
+async fn dav_propfind(
+    req: Request,
+    path: path::PathBuf,
+    fs: Arc<Remarkable>,
+    dav: Arc<Dav>,
+) -> Response {
+    // a missing depth means infinity
+    let depth = match req.headers().typed_try_get::<Depth>() {
+        Ok(depth) => depth.unwrap_or(Depth::Infinity),
+        Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+    };
+
+    let max_level = match depth {
+        Depth::Zero => 0,
+        Depth::One => 1,
+        Depth::Infinity if dav.infinite_depth => usize::MAX,
+        Depth::Infinity => {
+            return (
+                StatusCode::FORBIDDEN,
+                [(header::CONTENT_TYPE, XML_CONTENT_TYPE)],
+                FINITE_DEPTH_ERROR,
+            )
+                .into_response()
+        }
+    };
+
+    let propfind = match body::to_bytes(req.into_body(), MAX_XML_BODY).await {
+        Ok(body) => parse_propfind(body),
+        Err(_) => None,
+    };
+    let Some(propfind) = propfind else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let Some(resource) = Resource::at(&fs, &path).or_else(|| Resource::placeholder(&dav, &path))
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    // breadth first, so every folder is listed before what's in it
+    let mut listed = vec![(resource, 0)];
+    let mut next = 0;
+    while let Some((resource, level)) = listed.get(next) {
+        if *level < max_level {
+            let level = level + 1;
+            let children = resource.children(&fs).await;
+            listed.extend(children.into_iter().map(|child| (child, level)));
+        }
+        next += 1;
+    }
+
+    let responses = future::join_all(
+        listed
+            .iter()
+            .map(|(resource, _)| resource.propfind(&fs, &dav, &propfind)),
+    )
+    .await;
+
+    multistatus(responses.into_iter().flatten().collect())
+}
+
+/// Parse the body of a PROPFIND, where an empty body asks for every property.
+fn parse_propfind(body: Bytes) -> Option<Propfind> {
+    if body.trim_ascii().is_empty() {
+        return Some(Propfind::Allprop { include: None });
+    }
+
+    let mut xml = Value::from_xml(body).ok()?;
+
+    // properties to `include` with `allprop` can't be parsed, and they'd all be included anyway
+    if let Value::Map(root) = &mut xml {
+        for propfind in root.as_mut().values_mut() {
+            if let Value::Map(propfind) = propfind {
+                propfind
+                    .as_mut()
+                    .retain(|name, _| &*name.local_name != "include");
+            }
+        }
+    }
+
+    xml.to_map().ok()?.get::<Propfind>()?.ok()
+}
+
+/// Respond with a `207 Multi-Status` listing `responses`.
+fn multistatus(responses: Vec<elements::Response>) -> Response {
+    let multistatus = Multistatus {
+        response: responses,
+        responsedescription: None,
+    };
+
+    match multistatus.into_xml() {
+        Ok(xml) => (
+            StatusCode::MULTI_STATUS,
+            [(header::CONTENT_TYPE, XML_CONTENT_TYPE)],
+            xml,
+        )
+            .into_response(),
+        Err(err) => {
+            tracing::error!("failed to write multistatus: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// The root, a folder or a document, as WebDAV clients see it.
+struct Resource {
+    /// The path it's at, which for documents ends in the extension they're exported as.
+    path: path::PathBuf,
+    /// The folder or document, or `None` for the root and the folders in [`virtual_folders`].
+    element: Option<(Uuid, Arc<Element>)>,
+    /// How a document is served at `path`, or `None` for folders.
+    export: Option<Export>,
+}
+
+impl Resource {
+    /// The resource at `path`, like `Notes/Tester.pdf`.
+    fn at(fs: &Remarkable, path: &path::Path) -> Option<Self> {
+        let path = path.strip_prefix("/").unwrap_or(path).to_path_buf();
+
+        if path.as_os_str().is_empty() || virtual_folders(fs).any(|folder| path == *folder) {
+            return Some(Self {
+                path,
+                element: None,
+                export: None,
+            });
+        }
+
+        if let Some((uuid, element, export)) = export(fs, &path) {
+            return Some(Self {
+                path,
+                element: Some((uuid, element)),
+                export: Some(export),
+            });
+        }
+
+        let element = fs.element(&path).filter(|(_, element)| element.is_dir())?;
+
+        Some(Self {
+            path,
+            element: Some(element),
+            export: None,
+        })
+    }
+
+    /// The empty file uploaded to `path`, if it's one of the [`Dav::placeholders`].
+    fn placeholder(dav: &Dav, path: &path::Path) -> Option<Self> {
+        let path = placeholder(path);
+        let export = match path.extension()?.to_str()? {
+            "pdf" => Export::Pdf,
+            "epub" => Export::Epub,
+            _ => return None,
+        };
+
+        dav.placeholders.contains(&path).then_some(Self {
+            path,
+            element: None,
+            export: Some(export),
+        })
+    }
+
+    fn is_collection(&self) -> bool {
+        self.export.is_none()
+    }
+
+    /// The folders and documents in a folder, documents named with the extension they're
+    /// served with by default.
+    async fn children(&self, fs: &Remarkable) -> Vec<Self> {
+        if !self.is_collection() {
+            return Vec::new();
+        }
+
+        let mut children = match fs.list(&self.path).await {
+            Ok(children) => children,
+            Err(err) => {
+                tracing::warn!("failed to list {:?}: {err}", self.path);
+                return Vec::new();
+            }
+        };
+        children.sort_by(|(_, _, a), (_, _, b)| a.cmp(b));
+
+        let virtual_folders = match self.path.as_os_str().is_empty() {
+            true => virtual_folders(fs).collect(),
+            false => Vec::new(),
+        };
+
+        children
+            .into_iter()
+            .map(|(uuid, element, name)| {
+                let export = element.format().map(Export::default_for);
+                let name = match export {
+                    Some(export) => format!("{name}.{}", export.extension()),
+                    None => name,
+                };
+
+                Self {
+                    path: self.path.join(name),
+                    element: Some((uuid, element)),
+                    export,
+                }
+            })
+            .chain(virtual_folders.into_iter().map(|folder| Self {
+                path: folder.into(),
+                element: None,
+                export: None,
+            }))
+            .collect()
+    }
+
+    fn href(&self) -> Option<Href> {
+        let mut href = format!("/dav/{}", encode(&self.path));
+        if self.is_collection() && !href.ends_with('/') {
+            href.push('/');
+        }
+
+        href.parse::<Uri>().ok().map(Href)
+    }
+
+    /// Every property of the resource that `propfind` could ask for.
+    ///
+    /// Documents have to be exported to tell their length, so that's only done when it's
+    /// asked for, and only the name is given when just names are asked for.
+    async fn properties(&self, fs: &Remarkable, dav: &Dav, propfind: &Propfind) -> Properties {
+        let Some((uuid, element)) = &self.element else {
+            let mut props = Properties::new();
+            match self.export {
+                // a placeholder, empty until it's uploaded again
+                Some(export) => {
+                    props = props
+                        .with(properties::ResourceType::empty())
+                        .with(properties::ContentLength(0));
+                    if let Ok(content_type) = export.content_type().parse() {
+                        props = props.with(properties::ContentType(content_type));
+                    }
+                }
+                None => props = props.with(properties::ResourceType::collection()),
+            }
+
+            return props
+                .with(lock::LockDiscovery(
+                    dav.locks.on(&lock_path(fs, &self.path)),
+                ))
+                .with(lock::SupportedLock);
+        };
+
+        let mut props = Properties::new()
+            .with(properties::DisplayName(element.name().into()))
+            .with(match self.export {
+                Some(_) => properties::ResourceType::empty(),
+                None => properties::ResourceType::collection(),
+            });
+
+        if let Some(export) = self.export {
+            if let Ok(content_type) = export.content_type().parse() {
+                props = props.with(properties::ContentType(content_type));
+            }
+
+            match propfind {
+                Propfind::Prop(requested) if requested.getcontentlength().is_none() => {}
+                Propfind::Propname => props = props.with_name::<properties::ContentLength>(),
+                _ => match dav.export_size(fs, uuid, element, export).await {
+                    Ok(size) => props = props.with(properties::ContentLength(size)),
+                    Err(err) => tracing::warn!("failed to export {:?}: {err}", self.path),
+                },
+            }
+        }
+
+        props = props.with(properties::LastModified(element.last_modified().into()));
+        if let Some(created) = element.created() {
+            props = props.with(properties::CreationDate(created.into()));
+        }
+
+        props = props.with(props::Pinned(element.is_pinned()));
+        if let Some(document) = element.document() {
+            props = props.with(props::Tags(document.tags.clone()));
+        }
+
+        props
+            .with(properties::ETag(etag(uuid, element, self.export).into()))
+            .with(lock::LockDiscovery(
+                dav.locks.on(&lock_path(fs, &self.path)),
+            ))
+            .with(lock::SupportedLock)
+    }
+
+    /// The `<response>` to `propfind` for this resource, with a `404 Not Found` propstat
+    /// for any requested properties it doesn't have.
+    async fn propfind(
+        &self,
+        fs: &Remarkable,
+        dav: &Dav,
+        propfind: &Propfind,
+    ) -> Option<elements::Response> {
+        let href = self.href()?;
+
+        let mut props = match Value::from(self.properties(fs, dav, propfind).await) {
+            Value::Map(live) => live,
+            _ => ValueMap::new(),
+        };
+
+        if let Some((uuid, _)) = &self.element {
+            match props::read_dead(fs, uuid).await {
+                Ok(dead) => {
+                    for (name, value) in dead.as_ref() {
+                        props.as_mut().entry(name.clone()).or_insert(value.clone());
+                    }
+                }
+                Err(err) => {
+                    tracing::warn!("failed to read the properties of {:?}: {err}", self.path)
+                }
+            }
+        }
+
+        let (found, missing) = match propfind {
+            Propfind::Allprop { .. } => (props, ValueMap::new()),
+            Propfind::Propname => {
+                let mut names = ValueMap::new();
+                for name in props.as_ref().keys() {
+                    names.as_mut().insert(name.clone(), Value::Empty);
+                }
+                (names, ValueMap::new())
+            }
+            Propfind::Prop(requested) => {
+                let (mut found, mut missing) = (ValueMap::new(), ValueMap::new());
+                for name in requested.names() {
+                    match props.as_ref().get(name) {
+                        Some(value) => found.as_mut().insert(name.clone(), value.clone()),
+                        None => missing.as_mut().insert(name.clone(), Value::Empty),
+                    };
+                }
+                (found, missing)
+            }
+        };
+
+        let propstats = [(found, StatusCode::OK), (missing, StatusCode::NOT_FOUND)]
+            .into_iter()
+            .filter(|(props, _)| !props.as_ref().is_empty())
+            .map(|(props, status)| propstat(props, status))
+            .collect();
+
+        Some(elements::Response::Propstat {
+            href,
+            propstat: NonEmpty::from_vec(propstats)
+                .unwrap_or_else(|| NonEmpty::new(propstat(ValueMap::new(), StatusCode::OK))),
+            responsedescription: None,
+        })
+    }
+}
+
+fn propstat(props: ValueMap, status: StatusCode) -> Propstat {
+    let mut props = Value::Map(props);
+    props::prefix_namespaces(&mut props);
+
+    Propstat {
+        prop: Properties::try_from(&props).unwrap_or_default(),
+        status: Status(status),
+        responsedescription: None,
+    }
+}
+
+/// An entity tag that changes along with the element, and differs between the ways a document
+/// is exported.
+fn etag(uuid: &Uuid, element: &Element, export: Option<Export>) -> String {
+    let modified = element
+        .last_modified()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    match export {
+        Some(export) => format!("\"{uuid}-{modified}-{}\"", export.extension()),
+        None => format!("\"{uuid}-{modified}\""),
+    }
+}
+
+async fn dav_lock(
+    req: Request,
+    path: path::PathBuf,
+    fs: Arc<Remarkable>,
+    dav: Arc<Dav>,
+) -> Response {
+    // a missing depth means infinity
+    let deep = match req.headers().typed_try_get::<Depth>() {
+        Ok(None | Some(Depth::Infinity)) => true,
+        Ok(Some(Depth::Zero)) => false,
+        _ => return StatusCode::BAD_REQUEST.into_response(),
+    };
+    // clients list a few timeouts they'd like, which isn't parsed, so those get the default
+    let timeout = req.headers().typed_try_get::<Timeout>().ok().flatten();
+    let tokens = submitted_tokens(req.headers());
+    let locked = lock_path(&fs, &path);
+
+    let Ok(body) = body::to_bytes(req.into_body(), MAX_XML_BODY).await else {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    };
+
+    // an empty body refreshes a lock, given by the `If` header
+    if body.is_empty() {
+        let lock = tokens
+            .iter()
+            .find_map(|token| dav.locks.refresh(&locked, token, timeout));
+
+        return match lock {
+            Some(lock) => lock_response(&lock),
+            None => StatusCode::PRECONDITION_FAILED.into_response(),
+        };
+    }
+
+    let Some((scope, owner)) = lock::parse_lockinfo(body) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    // paths that don't exist yet can be locked, so they can be written to under the lock
+    let resource = Resource::at(&fs, &path);
+    if resource.is_none() && path.parent().and_then(|p| fs.parent_at(p)).is_none() {
+        return StatusCode::CONFLICT.into_response();
+    }
+    // documents have nothing in them to lock
+    let deep = deep
+        && resource
+            .as_ref()
+            .is_some_and(|resource| resource.is_collection());
+
+    match dav.locks.lock(&locked, scope, deep, owner, timeout) {
+        // locking a path that doesn't exist makes an empty resource there, which is created
+        Ok(lock) if resource.is_none() => {
+            tracing::info!("locked unmapped {path:?} as {}", lock.token);
+            (StatusCode::CREATED, lock_response(&lock)).into_response()
+        }
+        Ok(lock) => {
+            tracing::info!("locked {path:?} as {}", lock.token);
+            lock_response(&lock)
+        }
+        Err(conflict) => dav_error(StatusCode::LOCKED, "no-conflicting-lock", &conflict),
+    }
+}
+
+/// The answer to a LOCK that took or refreshed `lock`.
+fn lock_response(lock: &lock::Lock) -> Response {
+    let prop = Properties::new().with(lock::LockDiscovery(vec![lock.clone()]));
+
+    match prop.into_xml() {
+        Ok(xml) => (
+            [
+                (header::CONTENT_TYPE, XML_CONTENT_TYPE.to_string()),
+                (LOCK_TOKEN.clone(), format!("<{}>", lock.token_uri())),
+            ],
+            xml,
+        )
+            .into_response(),
+        Err(err) => {
+            tracing::error!("failed to write lock {}: {err}", lock.token);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn dav_unlock(
+    req: Request,
+    path: path::PathBuf,
+    fs: Arc<Remarkable>,
+    dav: Arc<Dav>,
+) -> Response {
+    let token = req
+        .headers()
+        .typed_get::<LockToken>()
+        .and_then(|LockToken(url)| lock::token(&url.0.to_string()));
+    let Some(token) = token else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let path = lock_path(&fs, &path);
+    let locks = dav.locks.on(&path);
+    match locks.iter().find(|lock| lock.token == token) {
+        Some(lock) if dav.locks.unlock(&path, &token) => {
+            tracing::info!("unlocked {:?}", lock.root);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        _ => StatusCode::CONFLICT.into_response(),
+    }
+}
+
+/// Evaluate the `If` header of a request, then make sure it was given the token of every lock
+/// on what it would change, returning the response refusing it if not.
+fn check_preconditions(
+    fs: &Remarkable,
+    dav: &Dav,
+    method: &Method,
+    path: &path::Path,
+    headers: &HeaderMap,
+) -> Option<Response> {
+    let Ok(condition) = headers.typed_try_get::<If>() else {
+        return Some(StatusCode::BAD_REQUEST.into_response());
+    };
+    if condition.is_some_and(|condition| !holds(fs, dav, &condition, path)) {
+        return Some(StatusCode::PRECONDITION_FAILED.into_response());
+    }
+
+    let tokens = submitted_tokens(headers);
+    for (path, deep) in changes(method, path, headers) {
+        if let Some(lock) = dav.locks.unheld(&lock_path(fs, &path), deep, &tokens) {
+            return Some(dav_error(
+                StatusCode::LOCKED,
+                "lock-token-submitted",
+                &lock.root,
+            ));
+        }
+    }
+
+    None
+}
+
+/// Whether any of the lists of conditions in an `If` header holds, for the resource it's
+/// tagged with or the one requested.
+fn holds(fs: &Remarkable, dav: &Dav, condition: &If, path: &path::Path) -> bool {
+    let lists: Vec<_> = match condition {
+        If::NoTagList(lists) => lists.iter().map(|list| (Some(path.into()), list)).collect(),
+        If::TaggedList(tagged) => tagged
+            .iter()
+            .flat_map(|(ResourceTag(uri), lists)| {
+                let path = dav_path(uri).ok();
+                lists.iter().map(move |list| (path.clone(), list))
+            })
+            .collect(),
+    };
+
+    lists.into_iter().any(|(path, conditions)| {
+        // resources on other servers don't have any state here
+        let Some(path) = path else {
+            return false;
+        };
+
+        conditions.iter().all(|condition| match condition {
+            Condition::StateToken { not, coded_url } => {
+                let token = lock::token(&coded_url.0.to_string());
+                let locks = dav.locks.on(&lock_path(fs, &path));
+                let locked = locks.iter().any(|l| Some(l.token) == token);
+                locked != *not
+            }
+            Condition::ETag {
+                not,
+                etag: expected,
+            } => {
+                let etag = Resource::at(fs, &path).and_then(|resource| {
+                    let (uuid, element) = resource.element?;
+                    Some(etag(&uuid, &element, resource.export))
+                });
+                (etag.as_ref() == Some(expected)) != *not
+            }
+        })
+    })
+}
+
+/// The path locks on `path` are kept under, which is the path of the element it finds. That
+/// way `Notes`, `Notes.pdf`, `Notes/1.svg` and `Favorites/Notes` are all locked together.
+///
+/// Paths that don't exist yet are kept without the extension of the document that'd be
+/// uploaded there, so a lock taken before uploading `Notes.pdf` covers `Notes` once it's in.
+fn lock_path(fs: &Remarkable, path: &path::Path) -> path::PathBuf {
+    let page = page_path(path, "svg").or_else(|| page_path(path, "png"));
+    let uuid = match page {
+        Some((document, _)) => fs.element(document).map(|(uuid, _)| uuid),
+        None => match fs.element(path) {
+            Some((uuid, _)) => Some(uuid),
+            None => export(fs, path).map(|(uuid, _, _)| uuid),
+        },
+    };
+    if let Some(path) = uuid.and_then(|uuid| fs.path_of(&uuid)) {
+        return path;
+    }
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let name = match path.extension().and_then(|e| e.to_str()) {
+                Some("pdf" | "epub") => path.file_stem().unwrap_or(name),
+                _ => name,
+            };
+            lock_path(fs, parent).join(name)
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+/// The lock tokens in the `If` header of a request.
+fn submitted_tokens(headers: &HeaderMap) -> Vec<Uuid> {
+    let lists = match headers.typed_get::<If>() {
+        Some(If::NoTagList(lists)) => lists.into_iter().collect(),
+        Some(If::TaggedList(tagged)) => tagged.into_iter().flat_map(|(_, lists)| lists).collect(),
+        None => Vec::new(),
+    };
+
+    lists
+        .into_iter()
+        .flatten()
+        .filter_map(|condition| match condition {
+            Condition::StateToken { coded_url, .. } => lock::token(&coded_url.0.to_string()),
+            Condition::ETag { .. } => None,
+        })
+        .collect()
+}
+
+/// The paths a request would change, and whether it would change everything in them too,
+/// which need the tokens of the locks on them.
+///
+/// Adding something to a folder or taking something out of it changes the folder as well.
+fn changes(method: &Method, path: &path::Path, headers: &HeaderMap) -> Vec<(path::PathBuf, bool)> {
+    let with_parent = |path: &path::Path, deep: bool| {
+        let parent = path.parent().map(|parent| (parent.to_path_buf(), false));
+        [(path.to_path_buf(), deep)].into_iter().chain(parent)
+    };
+    let destination = || destination(headers).ok().into_iter();
+
+    match *method {
+        Method::PUT => with_parent(path, false).collect(),
+        Method::DELETE => with_parent(path, true).collect(),
+        _ if method == MKCOL.as_ref() => with_parent(path, false).collect(),
+        _ if method == PROPPATCH.as_ref() => vec![(path.to_path_buf(), false)],
+        _ if method == COPY.as_ref() => destination()
+            .flat_map(|destination| with_parent(&destination, true).collect::<Vec<_>>())
+            .collect(),
+        _ if method == MOVE.as_ref() => with_parent(path, true)
+            .chain(
+                destination()
+                    .flat_map(|destination| with_parent(&destination, true).collect::<Vec<_>>()),
+            )
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// A response with `status` explaining which precondition the lock on `root` broke, from
+/// [rfc4918 16](http://www.webdav.org/specs/rfc4918.html#precondition.postcondition.xml.elements).
+fn dav_error(status: StatusCode, condition: &str, root: &path::Path) -> Response {
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<d:error xmlns:d="DAV:"><d:{condition}><d:href>/dav/{}</d:href></d:{condition}></d:error>"#,
+        encode(root)
+    );
+
+    (status, [(header::CONTENT_TYPE, XML_CONTENT_TYPE)], body).into_response()
+}
+
+async fn dav_mkcol(req: Request, path: path::PathBuf, fs: Arc<Remarkable>) -> Response {
+    let (Some(name), Some(parent)) = (path.file_name().and_then(|s| s.to_str()), path.parent())
+    else {
+        // the root always exists
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    };
+
+    // there's no request body defined for MKCOL
+    match body::to_bytes(req.into_body(), MAX_XML_BODY).await {
+        Ok(body) if body.is_empty() => {}
+        _ => return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response(),
+    }
+
+    let Some(parent) = fs.parent_at(parent) else {
+        return StatusCode::CONFLICT.into_response();
+    };
+
+    if Resource::at(&fs, &path).is_some() {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+
+    match fs.create_directory(parent, &names::decode(name)).await {
+        Ok(uuid) => {
+            tracing::info!("created folder {path:?} as {uuid}");
+            StatusCode::CREATED.into_response()
+        }
+        Err(err) => {
+            tracing::error!("failed to create folder {path:?}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn dav_move(req: Request, path: path::PathBuf, fs: Arc<Remarkable>) -> Response {
+    let (uuid, element, export) = match Resource::at(&fs, &path) {
+        Some(Resource {
+            element: Some((uuid, element)),
+            export,
+            ..
+        }) => (uuid, element, export),
+        // the root can't be moved
+        Some(_) => return StatusCode::FORBIDDEN.into_response(),
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let target = match Target::of(&fs, req.headers(), &path, (uuid, &element), export) {
+        Ok(target) => target,
+        Err(status) => return status.into_response(),
+    };
+
+    if let Err(status) = target.make_room(&fs).await {
+        return status.into_response();
+    }
+
+    match move_to(&fs, (uuid, &element), &target, in_favorites(&path)).await {
+        Ok(()) => target.created(),
+        Err(err) => {
+            tracing::error!("failed to move {path:?} to {:?}: {err}", target.path);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Move the element `uuid` to `target`, unpinning it if it's moved out of the favorites.
+async fn move_to(
+    fs: &Remarkable,
+    (uuid, element): (Uuid, &Element),
+    target: &Target,
+    from_favorites: bool,
+) -> eyre::Result<()> {
+    let Some(parent) = target.parent else {
+        // the favorites only list pinned elements, wherever they are
+        if element.name() != target.name {
+            fs.rename(uuid, &target.name).await?;
+        }
+        return fs.set_pinned(uuid, true).await;
+    };
+
+    if from_favorites && element.is_pinned() {
+        fs.set_pinned(uuid, false).await?;
+    }
+
+    let into_trash = match parent {
+        Parent::Root => false,
+        Parent::Trash => true,
+        Parent::Directory(folder) => fs.is_trashed(&folder),
+    };
+
+    match (fs.is_trashed(&uuid), into_trash) {
+        // the same as deleting it, so it can be restored like anything deleted
+        (false, true) if parent == Parent::Trash => {
+            if element.name() != target.name {
+                fs.rename(uuid, &target.name).await?;
+            }
+            fs.move_to_trash(uuid).await
+        }
+        // back into the folder it was in, if that's still there
+        (true, false) => fs.restore(uuid, parent, &target.name).await,
+        _ => fs.move_element(uuid, parent, &target.name).await,
+    }
+}
+
+async fn dav_copy(req: Request, path: path::PathBuf, fs: Arc<Remarkable>) -> Response {
+    let (uuid, element, export) = match Resource::at(&fs, &path) {
+        Some(Resource {
+            element: Some((uuid, element)),
+            export,
+            ..
+        }) => (uuid, element, export),
+        // the root can't be copied
+        Some(_) => return StatusCode::FORBIDDEN.into_response(),
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    // folders are copied with everything in them, unless asked not to
+    let recursive = match req.headers().typed_try_get::<Depth>() {
+        Ok(None | Some(Depth::Infinity)) => true,
+        Ok(Some(Depth::Zero)) => false,
+        _ => return StatusCode::BAD_REQUEST.into_response(),
+    };
+
+    let target = match Target::of(&fs, req.headers(), &path, (uuid, &element), export) {
+        Ok(target) => target,
+        Err(status) => return status.into_response(),
+    };
+
+    // a copy in the favorites would only be the original pinned
+    let Some(parent) = target.parent else {
+        return StatusCode::FORBIDDEN.into_response();
+    };
+
+    if let Err(status) = target.make_room(&fs).await {
+        return status.into_response();
+    }
+
+    match fs.copy_element(uuid, parent, &target.name, recursive).await {
+        Ok(copy) => {
+            tracing::info!("copied {path:?} to {:?} as {copy}", target.path);
+            target.created()
+        }
+        Err(err) => {
+            tracing::error!("failed to copy {path:?} to {:?}: {err}", target.path);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Where a COPY or MOVE puts a resource.
+struct Target {
+    /// The path under `/dav` it goes to.
+    path: path::PathBuf,
+    /// The folder it goes in, or `None` for the favorites, which it's only pinned to.
+    parent: Option<Parent>,
+    /// The name it gets there.
+    name: String,
+    /// Whatever is at `path` already, which it replaces.
+    replaced: Option<Uuid>,
+}
+
+impl Target {
+    /// The `Destination` of a COPY or MOVE of the element `uuid`, served at `source` as
+    /// `export`.
+    fn of(
+        fs: &Remarkable,
+        headers: &HeaderMap,
+        source: &path::Path,
+        (uuid, element): (Uuid, &Element),
+        export: Option<Export>,
+    ) -> Result<Self, StatusCode> {
+        let path = destination(headers)?;
+        let overwrite = headers.typed_get::<Overwrite>().unwrap_or_default();
+
+        let (Some(name), Some(parent)) = (path.file_name().and_then(|s| s.to_str()), path.parent())
+        else {
+            return Err(StatusCode::FORBIDDEN);
+        };
+        let parent = match fs.parent_at(parent) {
+            Some(parent) => Some(parent),
+            None if in_favorites(&path) => None,
+            None => return Err(StatusCode::CONFLICT),
+        };
+
+        // documents keep the name they're listed with, less the extension they're exported with
+        let shown = |name: &str| match export {
+            Some(export) => name
+                .strip_suffix(&format!(".{}", export.extension()))
+                .unwrap_or(name)
+                .to_string(),
+            None => name.to_string(),
+        };
+        let source = source
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+        // and what tells them apart from siblings sharing their name, unless that changes
+        let name = names::from_path(&shown(name), &shown(source), element.name());
+
+        // folders can't go into themselves
+        if let Some(Parent::Directory(folder)) = parent {
+            if folder == uuid || fs.is_within(&folder, &uuid) {
+                return Err(StatusCode::CONFLICT);
+            }
+        }
+
+        let replaced = Resource::at(fs, &path)
+            .and_then(|resource| resource.element)
+            .map(|(existing, _)| existing);
+
+        match replaced {
+            // nothing can replace itself
+            Some(existing) if existing == uuid => return Err(StatusCode::FORBIDDEN),
+            Some(_) if overwrite == Overwrite::F => return Err(StatusCode::PRECONDITION_FAILED),
+            _ => {}
+        }
+
+        Ok(Self {
+            name,
+            path,
+            parent,
+            replaced,
+        })
+    }
+
+    /// Delete whatever is being replaced, or only unpin it in the favorites.
+    async fn make_room(&self, fs: &Remarkable) -> Result<(), StatusCode> {
+        let Some(existing) = self.replaced else {
+            return Ok(());
+        };
+
+        let removed = match self.parent {
+            Some(_) => delete(fs, existing).await,
+            None => fs.set_pinned(existing, false).await,
+        };
+
+        removed.map_err(|err| {
+            tracing::error!("failed to replace {:?}: {err}", self.path);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    }
+
+    /// The response once done: `201 Created`, or `204 No Content` if something was replaced.
+    fn created(&self) -> Response {
+        match self.replaced {
+            Some(_) => StatusCode::NO_CONTENT.into_response(),
+            None => StatusCode::CREATED.into_response(),
+        }
+    }
+}
+
+/// The path under `/dav` that the `Destination` of a COPY or MOVE points to.
+fn destination(headers: &HeaderMap) -> Result<path::PathBuf, StatusCode> {
+    let Some(Destination(uri)) = headers.typed_get() else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    dav_path(&uri)
+}
+
+/// The path under `/dav` that `uri` points to.
+fn dav_path(uri: &Uri) -> Result<path::PathBuf, StatusCode> {
+    let Some(path) = decode(uri.path()) else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    // anywhere else is another server as far as WebDAV is concerned
+    match path::Path::new(&path).strip_prefix("/dav") {
+        Ok(path) => Ok(path.to_path_buf()),
+        Err(_) => Err(StatusCode::BAD_GATEWAY),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+
+    use super::*;
+    use crate::remarkable::{lines::Page, testing::TempDocuments};
+
+    /// A document directory of a test's own, served the way the router serves it.
+    struct Server {
+        docs: TempDocuments,
+        fs: Arc<Remarkable>,
+        dav: Arc<Dav>,
+    }
+
+    impl Server {
+        /// A server on an empty document directory, unique to `test`.
+        async fn new(test: &str) -> Self {
+            Self::on(TempDocuments::new(test)).await
+        }
+
+        /// A server on a copy of the sample documents.
+        async fn samples(test: &str) -> Self {
+            Self::on(TempDocuments::copy_of(test, "samples/v6")).await
+        }
+
+        async fn on(docs: TempDocuments) -> Self {
+            let fs = Arc::new(Remarkable::from_path(docs.path()).await);
+
+            Self {
+                docs,
+                fs,
+                dav: Arc::new(Dav::default()),
+            }
+        }
+
+        /// Send a request through [`handler`], preconditions and locks included.
+        fn send(
+            &self,
+            method: &str,
+            path: &str,
+            headers: &[(&str, &str)],
+            body: impl Into<body::Body>,
+        ) -> impl Future<Output = Response> {
+            let mut req = Request::builder().method(method);
+            for &(name, value) in headers {
+                req = req.header(name, value);
+            }
+            let req = req.body(body.into()).unwrap();
+
+            handler(
+                req.method().clone(),
+                Some(extract::Path(path.into())),
+                State(self.fs.clone()),
+                Extension(self.dav.clone()),
+                req,
+            )
+        }
+
+        async fn propfind(
+            &self,
+            depth: &str,
+            path: &str,
+            body: &'static str,
+        ) -> (StatusCode, String) {
+            let resp = self.send("PROPFIND", path, &[("depth", depth)], body).await;
+
+            (resp.status(), text(resp).await)
+        }
+    }
+
+    async fn text(resp: Response) -> String {
+        let body = body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn parses_propfind_bodies() {
+        let parse = |body: &'static str| parse_propfind(Bytes::from_static(body.as_bytes()));
+
+        assert_eq!(parse(""), Some(Propfind::Allprop { include: None }));
+        assert_eq!(
+            parse(r#"<propfind xmlns="DAV:"><propname/></propfind>"#),
+            Some(Propfind::Propname)
+        );
+        assert!(matches!(
+            parse(
+                r#"<d:propfind xmlns:d="DAV:"><d:allprop/><d:include><d:x/></d:include></d:propfind>"#
+            ),
+            Some(Propfind::Allprop { .. })
+        ));
+
+        let Some(Propfind::Prop(props)) =
+            parse(r#"<propfind xmlns="DAV:"><prop><getetag/><x xmlns="urn:x"/></prop></propfind>"#)
+        else {
+            panic!("expected named properties");
+        };
+        assert_eq!(props.names().count(), 2);
+
+        assert_eq!(parse("<propfind"), None);
+    }
+
+    #[tokio::test]
+    async fn lists_folders() {
+        let server = Server::samples("list").await;
+        let (status, body) = server.propfind("1", "/", "").await;
+
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        assert!(body.contains("<d:href>/dav/</d:href>"));
+        assert!(body.contains("<d:href>/dav/Tester.pdf</d:href>"));
+        assert!(body.contains("<d:getcontenttype>application/pdf</d:getcontenttype>"));
+        assert!(body.contains("<d:getcontentlength>"));
+    }
+
+    #[tokio::test]
+    async fn reports_missing_properties() {
+        let server = Server::samples("missing-properties").await;
+        let (status, body) = server
+            .propfind(
+                "0",
+                "/Tester.pdf",
+                r#"<propfind xmlns="DAV:"><prop><displayname/><x:color xmlns:x="urn:x"/></prop></propfind>"#,
+            )
+            .await;
+
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        assert!(body.contains("<d:displayname>Tester</d:displayname>"));
+        assert!(body.contains(":color/>"));
+        assert!(body.contains("404 Not Found"));
+    }
+
+    #[tokio::test]
+    async fn only_exports_for_the_length() {
+        let server = Server::samples("lengths").await;
+        let propfind = |body| server.propfind("1", "/", body);
+
+        let (_, names) = propfind(r#"<propfind xmlns="DAV:"><propname/></propfind>"#).await;
+        assert!(names.contains("<d:getcontentlength/>"));
+        propfind(r#"<propfind xmlns="DAV:"><prop><displayname/></prop></propfind>"#).await;
+        assert!(server.dav.export_sizes.is_empty());
+
+        propfind(r#"<propfind xmlns="DAV:"><prop><getcontentlength/></prop></propfind>"#).await;
+        assert_eq!(server.dav.export_sizes.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn refuses_infinite_depth() {
+        let server = Server::samples("infinite-depth").await;
+        let (status, body) = server.propfind("infinity", "/", "").await;
+
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(body.contains("propfind-finite-depth"));
+
+        let (status, _) = server.propfind("0", "/Missing", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn uploads_pdfs() {
+        let server = Server::new("put").await;
+        let fs = &server.fs;
+        let put = |path: &str, body: Vec<u8>| server.send("PUT", path, &[], body);
+
+        let pdf = pdf::render(&[Page::default()]).unwrap();
+        assert_eq!(
+            put("Paper.pdf", pdf.clone()).await.status(),
+            StatusCode::CREATED
+        );
+        assert_eq!(
+            put("Missing/Paper.pdf", pdf.clone()).await.status(),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            put("Broken.pdf", b"not a pdf".to_vec()).await.status(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+
+        // uploaded here, so it can be uploaded again
+        let (first, _) = fs.element("Paper").unwrap();
+        let two_pages = pdf::render(&[Page::default(), Page::default()]).unwrap();
+        assert_eq!(
+            put("Paper.pdf", two_pages).await.status(),
+            StatusCode::NO_CONTENT
+        );
+        let (second, element) = fs.element("Paper").unwrap();
+        assert_ne!(first, second);
+        let document = element.document().unwrap();
+        assert_eq!(document.format, Format::Pdf);
+        assert_eq!(document.page_count, Some(2));
+        assert_eq!(fs.list("/").await.unwrap().len(), 1);
+        assert!(fs.trash().is_empty());
+
+        // unlike what's on the tablet already
+        fs.import(Parent::Root, "Synced", Format::Pdf, &pdf, Some(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            put("Synced.pdf", pdf).await.status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+
+        // nothing's left of what was uploaded but not imported
+        let uploads = std::fs::read_dir(server.docs.path())
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().ends_with(".upload")
+            })
+            .count();
+        assert_eq!(uploads, 0);
+    }
+
+    #[tokio::test]
+    async fn uploads_in_place_of_empty_files() {
+        let server = Server::new("placeholder").await;
+        let fs = &server.fs;
+
+        // as Finder and Explorer do: an empty file first, then a lock on it, then what's in it
+        let resp = server.send("PUT", "New.pdf", &[], "").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert!(fs.element("New").is_none());
+
+        let (status, body) = server.propfind("0", "New.pdf", "").await;
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        assert!(body.contains("<d:getcontentlength>0</d:getcontentlength>"));
+
+        let lockinfo = r#"<lockinfo xmlns="DAV:"><lockscope><exclusive/></lockscope><locktype><write/></locktype></lockinfo>"#;
+        let resp = server.send("LOCK", "New.pdf", &[], lockinfo).await;
+        assert!(resp.status().is_success());
+        let token = resp.headers()["lock-token"].to_str().unwrap().to_string();
+        let condition = format!("({token})");
+
+        let pdf = pdf::render(&[Page::default()]).unwrap();
+        let resp = server.send("PUT", "New.pdf", &[], pdf.clone()).await;
+        assert_eq!(resp.status(), StatusCode::LOCKED);
+        let resp = server
+            .send("PUT", "New.pdf", &[("if", &condition)], pdf.clone())
+            .await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert!(fs.element("New").is_some() && server.dav.placeholders.is_empty());
+
+        // and again when it's saved
+        let resp = server
+            .send("PUT", "New.pdf", &[("if", &condition)], pdf)
+            .await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        // placeholders that are never filled in can be deleted
+        server.send("PUT", "Other.epub", &[], "").await;
+        let resp = server.send("DELETE", "Other.epub", &[], "").await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let (status, _) = server.propfind("0", "Other.epub", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn creates_folders() {
+        let server = Server::new("mkcol").await;
+        let fs = &server.fs;
+        let mkcol = |path: &str| server.send("MKCOL", path, &[], "");
+
+        assert_eq!(mkcol("Books").await.status(), StatusCode::CREATED);
+        assert_eq!(
+            mkcol("Books").await.status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(mkcol("Books/Fiction").await.status(), StatusCode::CREATED);
+        assert_eq!(mkcol("Papers/2024").await.status(), StatusCode::CONFLICT);
+        assert_eq!(mkcol("/").await.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let (books, _) = fs.element("Books").unwrap();
+        let (_, fiction) = fs.element("Books/Fiction").unwrap();
+        assert!(fiction.is_dir());
+        assert_eq!(fs.parent_at("Books"), Some(Parent::Directory(books)));
+    }
+
+    #[tokio::test]
+    async fn deletes_to_the_trash_first() {
+        let server = Server::new("delete").await;
+        let fs = &server.fs;
+        let delete = |path: &str| server.send("DELETE", path, &[], "");
+
+        let books = fs.create_directory(Parent::Root, "Books").await.unwrap();
+        let pdf = pdf::render(&[Page::default()]).unwrap();
+        let paper = fs
+            .import(
+                Parent::Directory(books),
+                "Paper",
+                Format::Pdf,
+                &pdf,
+                Some(1),
+            )
+            .await
+            .unwrap();
+
+        let resp = delete("Books").await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert!(fs.is_trashed(&books) && fs.is_trashed(&paper));
+        assert!(fs.list("/").await.unwrap().is_empty());
+
+        let resp = delete("Trash/Paper.pdf").await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert!(fs.element("Paper").is_none());
+        assert!(std::fs::read_dir(server.docs.path())
+            .unwrap()
+            .all(|entry| !entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with(&paper.to_string())));
+
+        let resp = delete("/").await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn moves_and_renames() {
+        let server = Server::new("move").await;
+        let fs = &server.fs;
+        let r#move = |path: &str, destination: &str, overwrite: &str| {
+            let headers = [("destination", destination), ("overwrite", overwrite)];
+            server.send("MOVE", path, &headers, "")
+        };
+
+        let pdf = pdf::render(&[Page::default()]).unwrap();
+        let a = fs.create_directory(Parent::Root, "A").await.unwrap();
+        let b = fs.create_directory(Parent::Root, "B").await.unwrap();
+        let mut notes = Vec::new();
+        for folder in [a, b] {
+            let parent = Parent::Directory(folder);
+            notes.push(
+                fs.import(parent, "Notes", Format::Pdf, &pdf, None)
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        let resp = r#move("A/Notes.pdf", "http://tablet/dav/B/My%20Notes.pdf", "T").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(fs.element("B/My Notes").unwrap().0, notes[0]);
+        assert!(fs.element("A/Notes").is_none());
+        assert_eq!(fs.element("B/Notes").unwrap().0, notes[1]);
+
+        let resp = r#move("B/Notes.pdf", "/dav/B/My%20Notes.pdf", "F").await;
+        assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+        let resp = r#move("B/Notes.pdf", "/dav/B/My%20Notes.pdf", "T").await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(fs.element("B/My Notes").unwrap().0, notes[1]);
+        assert!(fs.is_trashed(&notes[0]));
+
+        let resp = r#move("B", "/dav/B/Inner", "T").await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let resp = r#move("B", "/elsewhere/B", "T").await;
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+
+        let resp = r#move("B", "/dav/Trash/B", "T").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert!(fs.is_trashed(&b) && fs.is_trashed(&notes[1]));
+
+        // back where it was rather than where it's dropped, and what was replaced in it was
+        // trashed on its own, so it stays there
+        let resp = r#move("Trash/B", "/dav/A/B", "T").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(fs.element("B/My Notes").unwrap().0, notes[1]);
+        assert!(!fs.is_within(&b, &a));
+        assert!(fs.is_trashed(&notes[0]));
+
+        let resp = r#move("Trash/My Notes.pdf", "/dav/A/Notes.pdf", "T").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(fs.element("B/Notes").unwrap().0, notes[0]);
+
+        // unless where it was is gone
+        for path in ["B/Notes.pdf", "B", "Trash/B"] {
+            let resp = server.send("DELETE", path, &[], "").await;
+            assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        }
+        let resp = r#move("Trash/Notes.pdf", "/dav/A/Notes.pdf", "T").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(fs.element("A/Notes").unwrap().0, notes[0]);
+        assert!(fs.element("B").is_none());
+    }
+
+    #[tokio::test]
+    async fn tells_apart_siblings_sharing_names() {
+        let server = Server::new("duplicates").await;
+        let fs = &server.fs;
+        let pdf = pdf::render(&[Page::default()]).unwrap();
+        let mut notes = Vec::new();
+        for _ in 0..2 {
+            notes.push(
+                fs.import(Parent::Root, "Notes", Format::Pdf, &pdf, None)
+                    .await
+                    .unwrap(),
+            );
+        }
+        let folder = fs.create_directory(Parent::Root, "A").await.unwrap();
+
+        let (_, body) = server.propfind("1", "/", "").await;
+        assert!(body.contains("<d:href>/dav/Notes.pdf</d:href>"));
+        assert!(body.contains("<d:href>/dav/Notes%20%282%29.pdf</d:href>"));
+
+        let (first, _) = fs.element("Notes").unwrap();
+        let (second, _) = fs.element("Notes (2)").unwrap();
+        assert_ne!(first, second);
+        assert!(notes.contains(&first) && notes.contains(&second));
+
+        // moving it elsewhere keeps its name, which no longer needs telling apart there
+        let to_second = [("destination", "/dav/A/Notes%20(2).pdf")];
+        let resp = server.send("MOVE", "Notes (2).pdf", &to_second, "").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let (moved, element) = fs.element("A/Notes").unwrap();
+        assert_eq!((moved, element.name()), (second, "Notes"));
+        assert!(fs.is_within(&second, &folder));
+        assert_eq!(fs.element("Notes").unwrap().0, first);
+        assert!(fs.element("Notes (2)").is_none());
+
+        // while renaming it to what looks like that is taken as a name
+        let resp = server.send("MOVE", "A/Notes.pdf", &to_second, "").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let (renamed, element) = fs.element("A/Notes (2)").unwrap();
+        assert_eq!((renamed, element.name()), (second, "Notes (2)"));
+
+        // a folder named like a document is served is told apart from it, whichever is older
+        let older = fs.create_directory(Parent::Root, "X.pdf").await.unwrap();
+        let document = fs
+            .import(Parent::Root, "X", Format::Pdf, &pdf, None)
+            .await
+            .unwrap();
+        let folder = fs
+            .create_directory(Parent::Directory(older), "Y.pdf")
+            .await
+            .unwrap();
+        let inner = fs
+            .import(Parent::Directory(older), "Y", Format::Pdf, &pdf, None)
+            .await
+            .unwrap();
+        for (uuid, created) in [
+            (older, 1000),
+            (document, 2000),
+            (inner, 1000),
+            (folder, 2000),
+        ] {
+            server.docs.set_created(&uuid, created);
+        }
+        fs.index().await;
+
+        let (_, body) = server.propfind("1", "/", "").await;
+        assert!(body.contains("<d:href>/dav/X.pdf/</d:href>"));
+        assert!(body.contains("<d:href>/dav/X%20%282%29.pdf</d:href>"));
+        assert_eq!(export(fs, "X (2).pdf".as_ref()).unwrap().0, document);
+        assert_eq!(fs.element("X.pdf").unwrap().0, older);
+
+        assert_eq!(export(fs, "X.pdf/Y.pdf".as_ref()).unwrap().0, inner);
+        assert_eq!(fs.element("X.pdf/Y.pdf (2)").unwrap().0, folder);
+    }
+
+    #[tokio::test]
+    async fn keeps_names_paths_cant_hold() {
+        let server = Server::new("names").await;
+        let fs = &server.fs;
+        let pdf = pdf::render(&[Page::default()]).unwrap();
+
+        let resp = server.send("MKCOL", "Drafts：2024．", &[], "").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let resp = server
+            .send("PUT", "Drafts：2024．/a／b.pdf", &[], pdf)
+            .await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let (drafts, folder) = fs.element("Drafts：2024．").unwrap();
+        assert_eq!(folder.name(), "Drafts:2024.");
+        let (document, element) = fs.element("Drafts：2024．/a／b").unwrap();
+        assert_eq!(element.name(), "a/b");
+        assert!(fs.is_within(&document, &drafts));
+
+        let children = Resource::at(fs, "Drafts：2024．".as_ref())
+            .unwrap()
+            .children(fs)
+            .await;
+        let paths: Vec<_> = children.iter().map(|child| &child.path).collect();
+        assert_eq!(paths, [path::Path::new("Drafts：2024．/a／b.pdf")]);
+
+        // ？, percent-encoded
+        let destination = [("destination", "/dav/What%EF%BC%9F.pdf")];
+        let resp = server
+            .send("MOVE", "Drafts：2024．/a／b.pdf", &destination, "")
+            .await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(fs.element("What？").unwrap().1.name(), "What?");
+
+        // an empty name still has a path
+        let untitled = fs.create_directory(Parent::Root, "").await.unwrap();
+        assert_eq!(fs.element("‛").unwrap().0, untitled);
+        let root = Resource::at(fs, "/".as_ref()).unwrap();
+        let children = root.children(fs).await;
+        assert!(children
+            .iter()
+            .any(|child| child.path == path::Path::new("‛")));
+    }
+
+    #[tokio::test]
+    async fn mounts_favorites_and_trash() {
+        let server = Server::new("virtual").await;
+        let fs = &server.fs;
+
+        let pdf = pdf::render(&[Page::default()]).unwrap();
+        let a = fs.create_directory(Parent::Root, "A").await.unwrap();
+        let notes = fs
+            .import(Parent::Directory(a), "Notes", Format::Pdf, &pdf, None)
+            .await
+            .unwrap();
+
+        let (_, body) = server.propfind("1", "/", "").await;
+        assert!(body.contains("<d:href>/dav/Favorites/</d:href>"));
+        assert!(body.contains("<d:href>/dav/Trash/</d:href>"));
+
+        // into the favorites, staying where it is
+        let destination = [("destination", "/dav/Favorites/Notes.pdf")];
+        let resp = server.send("MOVE", "A/Notes.pdf", &destination, "").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let (pinned, element) = fs.element("Favorites/Notes").unwrap();
+        assert_eq!(pinned, notes);
+        assert!(element.is_pinned() && fs.is_within(&notes, &a));
+
+        let destination = [("destination", "/dav/Favorites/Copy.pdf")];
+        let resp = server.send("COPY", "A/Notes.pdf", &destination, "").await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let resp = server.send("DELETE", "Favorites/Notes.pdf", &[], "").await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert!(fs.element("Favorites/Notes").is_none());
+        assert!(!fs.element("A/Notes").unwrap().1.is_pinned());
+        assert!(!fs.is_trashed(&notes));
+
+        // out of the trash, with what was trashed along with it
+        let resp = server.send("DELETE", "A", &[], "").await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(fs.list("Trash").await.unwrap().len(), 2);
+
+        let destination = [("destination", "/dav/Restored")];
+        let resp = server.send("MOVE", "Trash/A", &destination, "").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(fs.element("Restored/Notes").unwrap().0, notes);
+        assert!(!fs.is_trashed(&notes) && fs.list("Trash").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn copies_documents_and_folders() {
+        let server = Server::samples("copy").await;
+        let (fs, dir) = (&server.fs, server.docs.path());
+        let copy = |path: &str, destination: &str, depth: &str| {
+            let headers = [("destination", destination), ("depth", depth)];
+            server.send("COPY", path, &headers, "")
+        };
+
+        let resp = copy("Tester.pdf", "/dav/Tester.pdf", "infinity").await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        fs.create_directory(Parent::Root, "Folder").await.unwrap();
+        let resp = copy("Tester.pdf", "/dav/Folder/Fork.pdf", "infinity").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let (original, tester) = fs.element("Tester").unwrap();
+        let (fork, element) = fs.element("Folder/Fork").unwrap();
+        assert_ne!(fork, original);
+
+        let pages = fs.read_pages(&original).await.unwrap();
+        let copied = fs.read_pages(&fork).await.unwrap();
+        assert_eq!(copied, pages);
+
+        let page = element.pages().unwrap()[0].id;
+        assert_ne!(page, tester.pages().unwrap()[0].id);
+        assert!(dir.join(format!("{fork}.thumbnails/{page}.png")).exists());
+        assert!(dir.join(format!("{fork}/{page}.rm")).exists());
+
+        let resp = copy("Folder", "/dav/Shallow", "0").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert!(fs.list("Shallow").await.unwrap().is_empty());
+
+        let resp = copy("Folder", "/dav/Deep", "infinity").await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let (deep, _) = fs.element("Deep/Fork").unwrap();
+        assert_ne!(deep, fork);
+
+        let resp = copy("Folder", "/dav/Deep", "infinity").await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let resp = copy("Folder", "/dav/Folder/Inner", "infinity").await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn locks_and_unlocks() {
+        let server = Server::new("lock").await;
+        let (fs, dav) = (&server.fs, &server.dav);
+        fs.create_directory(Parent::Root, "A").await.unwrap();
+
+        let lock = |path: &str, scope: &str| {
+            let body = format!(
+                r#"<lockinfo xmlns="DAV:"><lockscope><{scope}/></lockscope><locktype><write/></locktype></lockinfo>"#
+            );
+            server.send("LOCK", path, &[], body)
+        };
+        let check = |path: &str, condition: Option<String>| {
+            let mut headers = HeaderMap::new();
+            if let Some(condition) = condition {
+                headers.insert("if", condition.parse().unwrap());
+            }
+            check_preconditions(fs, dav, &Method::PUT, path::Path::new(path), &headers)
+                .map(|resp| resp.status())
+        };
+
+        let resp = lock("A", "exclusive").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let token = resp.headers()["lock-token"].to_str().unwrap().to_string();
+
+        assert_eq!(
+            lock("A/Notes.pdf", "shared").await.status(),
+            StatusCode::LOCKED
+        );
+        assert_eq!(check("A/Notes.pdf", None), Some(StatusCode::LOCKED));
+        assert_eq!(check("A/Notes.pdf", Some(format!("({token})"))), None);
+        assert_eq!(
+            check("A/Notes.pdf", Some(format!("({token} [\"other\"])"))),
+            Some(StatusCode::PRECONDITION_FAILED)
+        );
+        assert_eq!(check("B.pdf", None), None);
+
+        let unlock = |path: &str| server.send("UNLOCK", path, &[("lock-token", &token)], "");
+        assert_eq!(unlock("B").await.status(), StatusCode::CONFLICT);
+        assert_eq!(unlock("A/Notes.pdf").await.status(), StatusCode::NO_CONTENT);
+        assert_eq!(check("A/Notes.pdf", None), None);
+
+        // a document is locked however it's reached
+        let pdf = pdf::render(&[Page::default()]).unwrap();
+        let paper = fs
+            .import(Parent::Root, "Paper", Format::Pdf, &pdf, Some(1))
+            .await
+            .unwrap();
+        fs.set_pinned(paper, true).await.unwrap();
+        assert_eq!(lock("Paper", "exclusive").await.status(), StatusCode::OK);
+        assert_eq!(check("Paper.pdf", None), Some(StatusCode::LOCKED));
+        assert_eq!(
+            lock("Favorites/Paper.pdf", "shared").await.status(),
+            StatusCode::LOCKED
+        );
+        for path in ["Paper.pdf", "Paper/1.svg", "/Favorites/Paper"] {
+            assert_eq!(
+                lock_path(fs, path::Path::new(path)),
+                path::Path::new("Paper")
+            );
+        }
+
+        // as is a document that's yet to be uploaded
+        assert_eq!(
+            lock("New.pdf", "exclusive").await.status(),
+            StatusCode::CREATED
+        );
+        fs.import(Parent::Root, "New", Format::Pdf, &pdf, Some(1))
+            .await
+            .unwrap();
+        assert_eq!(check("New", None), Some(StatusCode::LOCKED));
+    }
+
+    #[tokio::test]
+    async fn only_downloads_documents() {
+        let server = Server::samples("get").await;
+        let get = |path: &str| server.send("GET", path, &[], "");
+
+        let resp = get("Tester.pdf").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/pdf");
+
+        let resp = get("/").await;
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert!(!resp.headers()[header::ALLOW]
+            .to_str()
+            .unwrap()
+            .contains("GET"));
+
+        assert_eq!(get("Missing").await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn answers_options() {
+        let server = Server::new("options").await;
+        let resp = server.send("OPTIONS", "/", &[], "").await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["dav"], "1, 2");
+        let allow = resp.headers()[header::ALLOW].to_str().unwrap();
+        for method in ["PUT", "COPY", "MOVE", "PROPPATCH", "LOCK", "UNLOCK"] {
+            assert!(allow.contains(method), "{allow}");
+        }
+    }
+
+    #[tokio::test]
+    async fn patches_properties() {
+        let server = Server::new("proppatch").await;
+        let fs = &server.fs;
+        let proppatch = |path: &str, body: &'static str| server.send("PROPPATCH", path, &[], body);
+
+        let pdf = pdf::render(&[Page::default()]).unwrap();
+        let uuid = fs
+            .import(Parent::Root, "Notes", Format::Pdf, &pdf, None)
+            .await
+            .unwrap();
+
+        let resp = proppatch(
+            "Notes.pdf",
+            r#"<propertyupdate xmlns="DAV:" xmlns:rm="urn:rm-webdav"><set><prop>
+                <displayname>Renamed</displayname>
+                <rm:pinned>true</rm:pinned>
+                <rm:tags><rm:tag>work</rm:tag></rm:tags>
+                <color xmlns="urn:x">blue</color>
+            </prop></set></propertyupdate>"#,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::MULTI_STATUS);
+
+        let (renamed, element) = fs.element("Renamed").unwrap();
+        assert_eq!(renamed, uuid);
+        assert!(element.is_pinned());
+        assert_eq!(element.document().unwrap().tags, ["work"]);
+        let dead = props::read_dead(fs, &uuid).await.unwrap();
+        assert_eq!(dead.as_ref().len(), 1);
+
+        let resp = proppatch(
+            "Renamed.pdf",
+            r#"<propertyupdate xmlns="DAV:" xmlns:rm="urn:rm-webdav">
+                <remove><prop><rm:pinned/></prop></remove>
+                <set><prop><getetag>"forged"</getetag></prop></set>
+            </propertyupdate>"#,
+        )
+        .await;
+        let body = text(resp).await;
+        assert!(body.contains("403 Forbidden") && body.contains("424 Failed Dependency"));
+        assert!(fs.element("Renamed").unwrap().1.is_pinned());
+
+        let (_, body) = server.propfind("0", "Renamed.pdf", "").await;
+        assert!(body.contains("<rm:tag>work</rm:tag>") && body.contains(">blue</"));
+    }
+}
@@ -0,0 +1,339 @@
+//! Properties beyond WebDAV's own: whether an element is pinned and a document's tags, which
+//! the tablet understands, and any others clients set, which are kept next to the element's
+//! files for them.
+
+use axum::body::Bytes;
+use color_eyre::eyre;
+use uuid::Uuid;
+use webdav::xml::{
+    elements::Properties, Element, Error, FromXml, IntoXml, Value, ValueMap, DAV_NAMESPACE,
+};
+
+use crate::remarkable::Remarkable;
+
+/// The namespace of the properties the tablet understands that WebDAV doesn't have.
+pub const RM_NAMESPACE: &str = "urn:rm-webdav";
+const RM_PREFIX: &str = "rm";
+
+/// The extension of the file the properties clients set are kept in, next to the element's.
+const SIDECAR_EXTENSION: &str = "davprops";
+
+/// What setting or removing a property changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Property {
+    /// `displayname`, the name the tablet shows.
+    DisplayName,
+    /// `rm:pinned`, whether the element is in the favorites.
+    Pinned,
+    /// `rm:tags`, the tags on a document.
+    Tags,
+    /// Any other property of WebDAV's or ours, which can't be changed.
+    Protected,
+    /// Anything else, which is only kept for clients to read back.
+    Dead,
+}
+
+impl Property {
+    /// The property named `local_name` in `namespace`.
+    pub fn named(namespace: Option<&str>, local_name: &str) -> Self {
+        match (namespace, local_name) {
+            (Some(DAV_NAMESPACE), "displayname") => Self::DisplayName,
+            (Some(RM_NAMESPACE), "pinned") => Self::Pinned,
+            (Some(RM_NAMESPACE), "tags") => Self::Tags,
+            (Some(DAV_NAMESPACE | RM_NAMESPACE), _) => Self::Protected,
+            _ => Self::Dead,
+        }
+    }
+}
+
+/// `rm:pinned`, `true` or `false`.
+pub struct Pinned(pub bool);
+
+impl Element for Pinned {
+    const NAMESPACE: &'static str = RM_NAMESPACE;
+    const PREFIX: &'static str = RM_PREFIX;
+    const LOCAL_NAME: &'static str = "pinned";
+}
+
+impl TryFrom<&Value> for Pinned {
+    type Error = Error;
+
+    fn try_from(value: &Value) -> Result<Self, Error> {
+        match value.to_str()?.trim() {
+            "true" | "1" => Ok(Self(true)),
+            "false" | "0" => Ok(Self(false)),
+            _ => Err(Error::InvalidValueType("expected true or false")),
+        }
+    }
+}
+
+impl From<Pinned> for Value {
+    fn from(Pinned(pinned): Pinned) -> Value {
+        Value::Text(pinned.to_string().into())
+    }
+}
+
+/// `rm:tags`, with an `rm:tag` for each tag.
+pub struct Tags(pub Vec<String>);
+
+impl Element for Tags {
+    const NAMESPACE: &'static str = RM_NAMESPACE;
+    const PREFIX: &'static str = RM_PREFIX;
+    const LOCAL_NAME: &'static str = "tags";
+}
+
+impl TryFrom<&Value> for Tags {
+    type Error = Error;
+
+    fn try_from(value: &Value) -> Result<Self, Error> {
+        let tags = match value {
+            Value::Empty => return Ok(Self(Vec::new())),
+            value => value.to_map()?.get::<Tag>(),
+        };
+
+        let tags = match tags {
+            Some(Ok(Tag(Value::List(tags)))) => tags
+                .iter()
+                .map(|tag| Ok(tag.to_str()?.to_string()))
+                .collect::<Result<_, Error>>()?,
+            Some(Ok(Tag(tag))) => vec![tag.to_str()?.to_string()],
+            Some(Err(err)) => return Err(err),
+            None => Vec::new(),
+        };
+
+        Ok(Self(tags))
+    }
+}
+
+impl From<Tags> for Value {
+    fn from(Tags(tags): Tags) -> Value {
+        let mut tags = tags.into_iter().map(Value::from);
+        let Some(first) = tags.next() else {
+            return Value::Empty;
+        };
+
+        let mut map = ValueMap::new();
+        map.insert::<Tag>(match tags.len() {
+            0 => first,
+            _ => Value::List(Box::new((first, tags.collect::<Vec<_>>()).into())),
+        });
+
+        Value::Map(map)
+    }
+}
+
+/// An `rm:tag`, or several of them in a row.
+struct Tag(Value);
+
+impl Element for Tag {
+    const NAMESPACE: &'static str = RM_NAMESPACE;
+    const PREFIX: &'static str = RM_PREFIX;
+    const LOCAL_NAME: &'static str = "tag";
+}
+
+impl TryFrom<&Value> for Tag {
+    type Error = Error;
+
+    fn try_from(value: &Value) -> Result<Self, Error> {
+        Ok(Self(value.clone()))
+    }
+}
+
+/// The properties a PROPPATCH sets, and the ones it removes.
+#[derive(Debug, Default)]
+pub struct PropertyUpdate {
+    pub set: ValueMap,
+    pub remove: ValueMap,
+}
+
+impl Element for PropertyUpdate {
+    const NAMESPACE: &'static str = DAV_NAMESPACE;
+    const PREFIX: &'static str = "d";
+    const LOCAL_NAME: &'static str = "propertyupdate";
+}
+
+impl TryFrom<&Value> for PropertyUpdate {
+    type Error = Error;
+
+    fn try_from(value: &Value) -> Result<Self, Error> {
+        let update = value.to_map()?;
+
+        Ok(Self {
+            set: update.get::<Set>().transpose()?.unwrap_or_default().0,
+            remove: update.get::<Remove>().transpose()?.unwrap_or_default().0,
+        })
+    }
+}
+
+/// `<set>` and `<remove>`, each with the `<prop>` they apply to, possibly several times over.
+macro_rules! instructions {
+    ($($name:ident = $tag:literal,)*) => {$(
+        #[derive(Default)]
+        struct $name(ValueMap);
+
+        impl Element for $name {
+            const NAMESPACE: &'static str = DAV_NAMESPACE;
+            const PREFIX: &'static str = "d";
+            const LOCAL_NAME: &'static str = $tag;
+        }
+
+        impl TryFrom<&Value> for $name {
+            type Error = Error;
+
+            fn try_from(value: &Value) -> Result<Self, Error> {
+                let instructions = match value {
+                    Value::List(list) => list.iter().collect(),
+                    value => vec![value],
+                };
+
+                let mut props = ValueMap::new();
+                for instruction in instructions {
+                    let prop = instruction
+                        .to_map()?
+                        .get::<Properties>()
+                        .ok_or(Error::MissingElement("prop"))??;
+                    if let Value::Map(prop) = Value::from(prop) {
+                        props.as_mut().extend(prop.as_ref().clone());
+                    }
+                }
+
+                Ok(Self(props))
+            }
+        }
+    )*};
+}
+
+instructions! {
+    Set = "set",
+    Remove = "remove",
+}
+
+/// Give every element in `value` the prefix of its namespace, as the XML writer would otherwise
+/// give the same one to every namespace read from a request, which can't tell them apart.
+pub fn prefix_namespaces(value: &mut Value) {
+    match value {
+        Value::Map(map) => {
+            let mut prefixed = ValueMap::new();
+            for (name, value) in map.as_ref() {
+                let (mut name, mut value) = (name.clone(), value.clone());
+                if let Some(namespace) = &name.namespace {
+                    name.prefix = Some(prefix(namespace).into());
+                }
+                prefix_namespaces(&mut value);
+                prefixed.as_mut().insert(name, value);
+            }
+            *map = prefixed;
+        }
+        Value::List(list) => list.iter_mut().for_each(prefix_namespaces),
+        Value::Empty | Value::Text(_) => {}
+    }
+}
+
+/// The prefix for `namespace`, which is always the same so responses listing properties
+/// of several resources never use it for another.
+fn prefix(namespace: &str) -> String {
+    match namespace {
+        DAV_NAMESPACE => "d".into(),
+        RM_NAMESPACE => RM_PREFIX.into(),
+        // FNV-1a, as it only needs to be stable
+        _ => {
+            let hash = namespace.bytes().fold(0x811c9dc5_u32, |hash, b| {
+                (hash ^ u32::from(b)).wrapping_mul(0x01000193)
+            });
+            format!("ns{hash:08x}")
+        }
+    }
+}
+
+/// The `<propertyupdate>` body of a PROPPATCH.
+pub fn parse_propertyupdate(body: Bytes) -> Option<PropertyUpdate> {
+    PropertyUpdate::from_xml(body)
+        .inspect_err(|err| tracing::debug!("invalid propertyupdate: {err}"))
+        .ok()
+}
+
+/// The properties clients set on the element `uuid` that mean nothing to the tablet.
+pub async fn read_dead(fs: &Remarkable, uuid: &Uuid) -> eyre::Result<ValueMap> {
+    let Some(xml) = fs.read_sidecar(uuid, SIDECAR_EXTENSION).await? else {
+        return Ok(ValueMap::new());
+    };
+
+    match Value::from(Properties::from_xml(xml)?) {
+        Value::Map(props) => Ok(props),
+        _ => Ok(ValueMap::new()),
+    }
+}
+
+/// Replace the properties clients set on the element `uuid`.
+pub async fn write_dead(fs: &Remarkable, uuid: &Uuid, props: ValueMap) -> eyre::Result<()> {
+    let mut props = Value::Map(props);
+    prefix_namespaces(&mut props);
+
+    let xml = Properties::try_from(&props)?.into_xml()?;
+    fs.write_sidecar(uuid, SIDECAR_EXTENSION, &xml).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_propertyupdates() {
+        let body = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:propertyupdate xmlns:D="DAV:" xmlns:rm="urn:rm-webdav" xmlns:Z="urn:z">
+  <D:set><D:prop><D:displayname>Renamed</D:displayname></D:prop></D:set>
+  <D:set><D:prop>
+    <rm:tags><rm:tag>reading</rm:tag><rm:tag>work</rm:tag></rm:tags>
+    <Z:Author>Someone</Z:Author>
+  </D:prop></D:set>
+  <D:remove><D:prop><rm:pinned/></D:prop></D:remove>
+</D:propertyupdate>"#;
+
+        let update = parse_propertyupdate(Bytes::from_static(body.as_bytes())).unwrap();
+        let names = |props: &ValueMap| {
+            props
+                .as_ref()
+                .keys()
+                .map(|name| Property::named(name.namespace.as_deref(), &name.local_name))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(&update.set),
+            [Property::DisplayName, Property::Tags, Property::Dead]
+        );
+        assert_eq!(names(&update.remove), [Property::Pinned]);
+
+        let tags = update.set.get::<Tags>().unwrap().unwrap();
+        assert_eq!(tags.0, ["reading", "work"]);
+    }
+
+    #[test]
+    fn prefixes_every_namespace() {
+        let body = r#"<propertyupdate xmlns="DAV:"><set><prop><a xmlns="urn:a"><b xmlns="urn:b"/></a><c xmlns="urn:c"/></prop></set></propertyupdate>"#;
+        let update = parse_propertyupdate(Bytes::from_static(body.as_bytes())).unwrap();
+
+        let mut props = Value::Map(update.set);
+        prefix_namespaces(&mut props);
+        let xml = Properties::try_from(&props).unwrap().into_xml().unwrap();
+        let xml = String::from_utf8(xml.to_vec()).unwrap();
+
+        for namespace in ["urn:a", "urn:b", "urn:c"] {
+            let declaration = format!(r#"xmlns:{}="{namespace}""#, prefix(namespace));
+            assert!(xml.contains(&declaration), "{xml}");
+        }
+        assert!(Properties::from_xml(xml).is_ok());
+    }
+
+    #[test]
+    fn roundtrips_tags() {
+        for tags in [
+            vec![],
+            vec!["one".to_string()],
+            vec!["a".into(), "b".into()],
+        ] {
+            let value = Value::from(Tags(tags.clone()));
+            assert_eq!(Tags::try_from(&value).unwrap().0, tags);
+        }
+    }
+}
@@ -13,6 +13,7 @@ use crate::remarkable::Remarkable;
 
 mod dav;
 mod remarkable;
+mod render;
 mod web;
 
 /// A web interface/webdav proxy for the reMarkable tablet
@@ -29,6 +30,10 @@ struct Args {
     /// the path to the reMarkable document directory
     #[argh(option, short = 'd', default = "default_doc_path()")]
     documents: PathBuf,
+
+    /// answer WebDAV PROPFIND requests with `Depth: infinity`, which list every document at once
+    #[argh(switch)]
+    infinite_depth: bool,
 }
 
 #[tokio::main]
@@ -49,7 +54,7 @@ async fn main() -> color_eyre::Result<()> {
 async fn http_server(args: &Args, state: Arc<Remarkable>) -> color_eyre::Result<()> {
     let app = Router::new()
         .merge(web::router())
-        .merge(dav::router())
+        .merge(dav::router(dav::Dav::new(args.infinite_depth)))
         .with_state(state)
         .layer(
             ServiceBuilder::new()
@@ -89,11 +94,11 @@ async fn http_server(args: &Args, state: Arc<Remarkable>) -> color_eyre::Result<
 }
 
 pub fn is_remarkable() -> bool {
-    return cfg!(all(
+    cfg!(all(
         target_arch = "arm",
         target_env = "musl",
         target_os = "linux"
-    ));
+    ))
 }
 
 /// Try to guess a good default document path based on the OS
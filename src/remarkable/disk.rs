@@ -1,27 +1,28 @@
 //! Utilities for reading from the reMarkable operating system.
 
 use std::{
-    path::{Path, PathBuf},
+    path::Path,
     str::FromStr,
+    time::{Duration, SystemTime},
 };
 
 use color_eyre::eyre;
-use serde_json::Value;
+use serde_json::{Map, Number, Value};
 use tokio::fs;
 use uuid::Uuid;
 
-use super::{Document, Element, ElementKind, Format, Parent};
+use super::{order, Document, DocumentPage, Element, ElementKind, Format, Orientation, Parent};
 
 pub const METADATA_EXTENSION: &str = "metadata";
 pub const CONTENT_EXTENSION: &str = "content";
 
-pub async fn read(base: &PathBuf, uuid: &Uuid) -> eyre::Result<Element> {
+pub async fn read(base: &Path, uuid: &Uuid) -> eyre::Result<Element> {
     // read metadata
     let meta = Metadata::from_disk(base, uuid).await?;
 
     let kind: ElementKind = match meta.kind {
         ElementType::Document => {
-            ElementKind::Document(Content::from_disk(base, &uuid).await?.into())
+            ElementKind::Document(Box::new(Content::from_disk(base, uuid).await?.into()))
         }
         ElementType::Directory => ElementKind::Directory,
     };
@@ -30,6 +31,15 @@ pub async fn read(base: &PathBuf, uuid: &Uuid) -> eyre::Result<Element> {
         name: meta.name,
         parent: meta.parent,
         pinned: meta.pinned,
+        created: meta.created,
+        last_modified: meta.last_modified,
+        last_opened: meta.last_opened,
+        last_opened_page: meta.last_opened_page,
+        deleted: meta.deleted,
+        metadata_modified: meta.metadata_modified,
+        modified: meta.modified,
+        synced: meta.synced,
+        version: meta.version,
         kind,
     })
 }
@@ -51,9 +61,132 @@ pub struct Metadata {
     kind: ElementType,
     #[serde(rename = "visibleName")]
     name: String,
+    #[serde(
+        rename = "createdTime",
+        with = "optional_millis",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    created: Option<SystemTime>,
+    #[serde(rename = "lastModified", with = "millis", default = "SystemTime::now")]
+    last_modified: SystemTime,
+    #[serde(
+        rename = "lastOpened",
+        with = "optional_millis",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    last_opened: Option<SystemTime>,
+    #[serde(
+        rename = "lastOpenedPage",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    last_opened_page: Option<u32>,
+    /// Deleted on the tablet, but not yet removed by a sync.
+    #[serde(default)]
+    deleted: bool,
+    /// Changed since the last sync.
+    #[serde(rename = "metadatamodified", default)]
+    metadata_modified: bool,
+    #[serde(default)]
+    modified: bool,
+    #[serde(default)]
+    synced: bool,
+    #[serde(default)]
+    version: u32,
+    /// Everything else, kept so rewriting the file doesn't lose anything.
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+/// (De)serialization of timestamps stored as strings of milliseconds since the unix epoch.
+mod millis {
+    use super::*;
+
+    pub fn serialize<S: serde::Serializer>(time: &SystemTime, s: S) -> Result<S::Ok, S::Error> {
+        let millis = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        s.serialize_str(&millis.to_string())
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(d: D) -> Result<SystemTime, D::Error> {
+        let millis = match <Value as serde::Deserialize>::deserialize(d)? {
+            Value::String(s) => s.parse().map_err(serde::de::Error::custom)?,
+            Value::Number(n) => n.as_u64().unwrap_or_default(),
+            other => {
+                return Err(serde::de::Error::custom(format!(
+                    "invalid timestamp {other}"
+                )))
+            }
+        };
+
+        Ok(SystemTime::UNIX_EPOCH + Duration::from_millis(millis))
+    }
+}
+
+/// Like [`millis`], for timestamps that may be missing, empty or zero.
+mod optional_millis {
+    use super::*;
+
+    pub fn serialize<S: serde::Serializer>(
+        time: &Option<SystemTime>,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => millis::serialize(time, s),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        d: D,
+    ) -> Result<Option<SystemTime>, D::Error> {
+        let time = match <Value as serde::Deserialize>::deserialize(d)? {
+            Value::Null => return Ok(None),
+            Value::String(s) if s.is_empty() => return Ok(None),
+            other => millis::deserialize(other).map_err(serde::de::Error::custom)?,
+        };
+
+        Ok(Some(time).filter(|t| *t != SystemTime::UNIX_EPOCH))
+    }
 }
 
 impl Metadata {
+    /// The metadata of a new document named `name` in `parent`, created now.
+    pub fn document(name: String, parent: Parent) -> Self {
+        Self::new(name, parent, ElementType::Document)
+    }
+
+    /// The metadata of a new folder named `name` in `parent`, created now.
+    pub fn directory(name: String, parent: Parent) -> Self {
+        Self::new(name, parent, ElementType::Directory)
+    }
+
+    fn new(name: String, parent: Parent, kind: ElementType) -> Self {
+        let now = SystemTime::now();
+
+        Self {
+            parent,
+            pinned: false,
+            kind,
+            name,
+            created: Some(now),
+            last_modified: now,
+            last_opened: None,
+            last_opened_page: None,
+            deleted: false,
+            metadata_modified: false,
+            modified: false,
+            synced: false,
+            version: 0,
+            other: Map::new(),
+        }
+    }
+
     pub async fn from_disk(base: &Path, uuid: &Uuid) -> eyre::Result<Self> {
         let mut path = base.join(uuid.to_string());
         path.set_extension(METADATA_EXTENSION);
@@ -77,13 +210,170 @@ impl Metadata {
 }
 
 /// Representation of \<BASE\>/\<UUID\>.content
-#[derive(serde::Deserialize, serde::Serialize, Eq, PartialEq, Debug, Copy, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct Content {
     #[serde(rename = "fileType")]
     format: Format,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    format_version: Option<u32>,
+    #[serde(rename = "cPages", default)]
+    pages: ContentPages,
+    /// Page IDs in order, used instead of `cPages` by format version 1.
+    #[serde(rename = "pages", default, skip_serializing_if = "Option::is_none")]
+    legacy_pages: Option<Vec<Uuid>>,
+    /// The original page each of `legacy_pages` shows, or -1 for inserted pages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    redirection_page_map: Option<Vec<i64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    page_count: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    orientation: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    margins: Option<Number>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text_scale: Option<Number>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    font_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    line_height: Option<Number>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    zoom_mode: Option<String>,
+    #[serde(default)]
+    tags: Vec<Tag>,
+    #[serde(default)]
+    page_tags: Vec<PageTag>,
+    #[serde(default, with = "lenient_u64", skip_serializing_if = "Option::is_none")]
+    size_in_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cover_page_number: Option<i64>,
+    #[serde(default)]
+    document_metadata: Map<String, Value>,
+    #[serde(default)]
+    extra_metadata: Map<String, Value>,
+    /// Everything else, kept so rewriting the file doesn't lose anything.
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+/// Representation of \<BASE\>/\<UUID\>.content for folders, which only have tags.
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Debug, Clone, Default)]
+pub struct DirectoryContent {
+    #[serde(default)]
+    tags: Vec<Tag>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentPages {
+    #[serde(default)]
+    pages: Vec<ContentPage>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Debug, Clone)]
+pub struct ContentPage {
+    id: Uuid,
+    /// Fractional index the pages are sorted by.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    idx: Option<Timestamped<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template: Option<Timestamped<String>>,
+    /// The page of the original PDF or EPUB this page shows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    redirect: Option<Timestamped<i64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deleted: Option<Timestamped<Value>>,
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+impl ContentPage {
+    fn is_deleted(&self) -> bool {
+        match &self.deleted {
+            Some(deleted) => {
+                !matches!(deleted.value, Value::Null | Value::Bool(false))
+                    && deleted.value.as_i64() != Some(0)
+            }
+            None => false,
+        }
+    }
+}
+
+/// A value along with the CRDT timestamp it was last set at.
+#[derive(serde::Deserialize, serde::Serialize, Eq, PartialEq, Debug, Clone)]
+pub struct Timestamped<T> {
+    timestamp: String,
+    value: T,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Debug, Clone)]
+pub struct Tag {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PageTag {
+    name: String,
+    page_id: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+}
+
+/// (De)serialization of integers that may be stored as strings.
+mod lenient_u64 {
+    use super::*;
+
+    pub fn serialize<S: serde::Serializer>(n: &Option<u64>, s: S) -> Result<S::Ok, S::Error> {
+        match n {
+            Some(n) => s.serialize_str(&n.to_string()),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<u64>, D::Error> {
+        Ok(match <Value as serde::Deserialize>::deserialize(d)? {
+            Value::String(s) => s.parse().ok(),
+            Value::Number(n) => n.as_u64(),
+            _ => None,
+        })
+    }
 }
 
 impl Content {
+    /// The content of a new PDF or EPUB document, imported from a file of `size` bytes.
+    ///
+    /// Pages are left for the tablet to list once the document is first opened.
+    pub fn imported(format: Format, page_count: Option<u32>, size: u64) -> Self {
+        Self {
+            format,
+            format_version: Some(2),
+            pages: ContentPages::default(),
+            legacy_pages: None,
+            redirection_page_map: None,
+            page_count,
+            orientation: Some("portrait".into()),
+            margins: None,
+            text_scale: None,
+            font_name: None,
+            line_height: None,
+            zoom_mode: None,
+            tags: Vec::new(),
+            page_tags: Vec::new(),
+            size_in_bytes: Some(size),
+            cover_page_number: Some(0),
+            document_metadata: Map::new(),
+            extra_metadata: Map::new(),
+            other: Map::new(),
+        }
+    }
+
     pub async fn from_disk(base: &Path, uuid: &Uuid) -> eyre::Result<Self> {
         let mut path = base.join(uuid.to_string());
         path.set_extension(CONTENT_EXTENSION);
@@ -106,26 +396,420 @@ impl Content {
     }
 }
 
-impl Into<Document> for Content {
-    fn into(self) -> Document {
+impl From<Content> for Document {
+    fn from(content: Content) -> Self {
+        let pages = match content.legacy_pages {
+            Some(ids) if content.pages.pages.is_empty() => {
+                let redirects = content.redirection_page_map.unwrap_or_default();
+
+                ids.into_iter()
+                    .enumerate()
+                    .map(|(i, id)| DocumentPage {
+                        id,
+                        redirect: redirects.get(i).and_then(|r| u32::try_from(*r).ok()),
+                        idx: None,
+                        template: None,
+                    })
+                    .collect()
+            }
+            _ => {
+                let mut pages = content.pages.pages;
+                order::sort_by_index(&mut pages, |p| {
+                    p.idx
+                        .as_ref()
+                        .map(|i| (i.value.as_str(), i.timestamp.as_str()))
+                });
+
+                pages
+                    .into_iter()
+                    .filter(|p| !p.is_deleted())
+                    .map(|p| DocumentPage {
+                        id: p.id,
+                        redirect: p.redirect.and_then(|r| u32::try_from(r.value).ok()),
+                        idx: p.idx.map(|i| i.value),
+                        template: p.template.map(|t| t.value).filter(|t| !t.is_empty()),
+                    })
+                    .collect()
+            }
+        };
+
+        let page_tags = content
+            .page_tags
+            .into_iter()
+            .map(|t| (t.page_id, t.name))
+            .collect();
+
         Document {
-            format: self.format,
+            format: content.format,
+            page_count: content.page_count,
+            pages,
+            orientation: match content.orientation.as_deref() {
+                Some("portrait") => Some(Orientation::Portrait),
+                Some("landscape") => Some(Orientation::Landscape),
+                _ => None,
+            },
+            margins: content.margins.and_then(|n| n.as_f64()),
+            text_scale: content.text_scale.and_then(|n| n.as_f64()),
+            font_name: content.font_name.filter(|f| !f.is_empty()),
+            // negative values mean the default
+            line_height: content
+                .line_height
+                .and_then(|n| n.as_f64())
+                .filter(|h| *h > 0.0),
+            zoom_mode: content.zoom_mode,
+            tags: content.tags.into_iter().map(|t| t.name).collect(),
+            page_tags,
+            size_in_bytes: content.size_in_bytes,
+            cover_page: content
+                .cover_page_number
+                .and_then(|n| u32::try_from(n).ok()),
+            document_metadata: content.document_metadata,
+            extra_metadata: content.extra_metadata,
+        }
+    }
+}
+
+/// Write `value` as the JSON file of `uuid` with `extension`, like `.metadata`.
+pub async fn write(
+    base: &Path,
+    uuid: &Uuid,
+    extension: &str,
+    value: &impl serde::Serialize,
+) -> eyre::Result<()> {
+    let mut path = base.join(uuid.to_string());
+    path.set_extension(extension);
+
+    fs::write(path, serde_json::to_string_pretty(value)?).await?;
+
+    Ok(())
+}
+
+/// Delete every file of the element `uuid`: its `.metadata`, `.content`, pages, thumbnails,
+/// original PDF or EPUB and anything else named after it.
+pub async fn remove(base: &Path, uuid: &Uuid) -> eyre::Result<()> {
+    let uuid = uuid.to_string();
+
+    // the metadata goes first, as it's what makes the element show up
+    let mut metadata = base.join(&uuid);
+    metadata.set_extension(METADATA_EXTENSION);
+    fs::remove_file(&metadata).await?;
+
+    let mut dir = fs::read_dir(base).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name != uuid && !name.starts_with(&format!("{uuid}.")) {
+            continue;
+        }
+
+        if entry.file_type().await?.is_dir() {
+            fs::remove_dir_all(entry.path()).await?;
+        } else {
+            fs::remove_file(entry.path()).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy every file of the element `from` to a new element `to`, named `name` in `parent`.
+///
+/// Pages get new IDs, which are rewritten in the `.content` and in the names of the files
+/// for each page, so the copy can be edited without touching the original.
+pub async fn copy(
+    base: &Path,
+    from: &Uuid,
+    to: &Uuid,
+    parent: Parent,
+    name: &str,
+) -> eyre::Result<()> {
+    let mut metadata = Metadata::from_disk(base, from).await?;
+
+    let mut ids = vec![(from.to_string(), to.to_string())];
+    if metadata.kind == ElementType::Document {
+        let content = Content::from_disk(base, from).await?;
+        let pages = content.pages.pages.iter().map(|p| p.id);
+        for page in pages.chain(content.legacy_pages.into_iter().flatten()) {
+            ids.push((page.to_string(), Uuid::new_v4().to_string()));
+        }
+    }
+    let rewrite = |text: &str| {
+        ids.iter()
+            .fold(text.to_string(), |text, (old, new)| text.replace(old, new))
+    };
+
+    let from = from.to_string();
+    let mut dir = fs::read_dir(base).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if file_name != from && !file_name.starts_with(&format!("{from}.")) {
+            continue;
+        }
+
+        let target = base.join(rewrite(&file_name));
+        if entry.file_type().await?.is_dir() {
+            // pages, thumbnails and the like, one file per page
+            fs::create_dir(&target).await?;
+            let mut files = fs::read_dir(entry.path()).await?;
+            while let Some(file) = files.next_entry().await? {
+                let file_name = rewrite(&file.file_name().to_string_lossy());
+                fs::copy(file.path(), target.join(file_name)).await?;
+            }
+        } else if file_name == format!("{from}.{CONTENT_EXTENSION}") {
+            fs::write(target, rewrite(&fs::read_to_string(entry.path()).await?)).await?;
+        } else if file_name != format!("{from}.{METADATA_EXTENSION}") {
+            fs::copy(entry.path(), target).await?;
         }
     }
+
+    // the metadata goes last, as it's what makes the copy show up
+    let now = SystemTime::now();
+    metadata.parent = parent;
+    metadata.name = name.to_string();
+    metadata.created = Some(now);
+    metadata.last_modified = now;
+    metadata.last_opened = None;
+    metadata.deleted = false;
+    metadata.metadata_modified = false;
+    metadata.modified = false;
+    metadata.synced = false;
+    metadata.version = 0;
+    write(base, to, METADATA_EXTENSION, &metadata).await
 }
 
 pub async fn change_parent(base: impl AsRef<Path>, uuid: &Uuid, parent: Parent) -> eyre::Result<()> {
-    let mut path = base.as_ref().join(uuid.to_string());
+    set_metadata(base.as_ref(), uuid, "parent", serde_json::to_value(parent)?).await
+}
+
+pub async fn rename(base: &Path, uuid: &Uuid, name: &str) -> eyre::Result<()> {
+    set_metadata(base, uuid, "visibleName", name.into()).await
+}
+
+pub async fn set_pinned(base: &Path, uuid: &Uuid, pinned: bool) -> eyre::Result<()> {
+    set_metadata(base, uuid, "pinned", pinned.into()).await
+}
+
+/// Replace the tags in the `.content` of the document `uuid`, keeping when the ones it
+/// already had were added.
+pub async fn set_tags(base: &Path, uuid: &Uuid, tags: &[String]) -> eyre::Result<()> {
+    let mut content = Content::from_disk(base, uuid).await?;
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    content.tags = tags
+        .iter()
+        .map(|name| {
+            let existing = content.tags.iter().find(|tag| tag.name == *name);
+            Tag {
+                name: name.clone(),
+                timestamp: existing.map_or(Some(now), |tag| tag.timestamp),
+            }
+        })
+        .collect();
+
+    write(base, uuid, CONTENT_EXTENSION, &content).await
+}
+
+/// Set `key` in the `.metadata` of `uuid` to `value`, keeping everything else as it is.
+async fn set_metadata(base: &Path, uuid: &Uuid, key: &str, value: Value) -> eyre::Result<()> {
+    let mut path = base.join(uuid.to_string());
     path.set_extension(METADATA_EXTENSION);
 
     let mut disk_value: Value = serde_json::from_slice(&fs::read(&path).await?)?;
 
-    let parent = serde_json::to_value(parent)?;
-    if let Some(disk_parent) = disk_value.get_mut("parent") {
-        *disk_parent = parent;
-    }
+    match disk_value.as_object_mut() {
+        Some(metadata) => metadata.insert(key.to_string(), value),
+        None => return Err(eyre::eyre!("{path:?} isn't an object")),
+    };
 
     fs::write(path, serde_json::to_string_pretty(&disk_value)?).await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_CONTENT: &str =
+        include_str!("../../samples/v6/1dc81a48-ecf8-4c11-a3e4-65dda27270a3.content");
+    const SAMPLE_METADATA: &str =
+        include_str!("../../samples/v6/1dc81a48-ecf8-4c11-a3e4-65dda27270a3.metadata");
+
+    fn millis(millis: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn reads_sample_metadata() {
+        let meta: Metadata = serde_json::from_str(SAMPLE_METADATA).unwrap();
+
+        assert_eq!(meta.name, "Tester");
+        assert_eq!(meta.kind, ElementType::Document);
+        assert_eq!(meta.created, Some(millis(1711491490126)));
+        assert_eq!(meta.last_modified, millis(1711492056839));
+        assert_eq!(meta.last_opened, Some(millis(1711492382709)));
+        assert_eq!(meta.last_opened_page, Some(0));
+        assert!(!meta.deleted);
+
+        let written = serde_json::to_value(&meta).unwrap();
+        let original: Value = serde_json::from_str(SAMPLE_METADATA).unwrap();
+        for (key, value) in original.as_object().unwrap() {
+            assert_eq!(written.get(key), Some(value), "{key}");
+        }
+    }
+
+    #[test]
+    fn reads_sync_state() {
+        let meta: Metadata = serde_json::from_str(
+            r#"{
+                "deleted": true,
+                "lastModified": "1711492056839",
+                "lastOpened": "0",
+                "metadatamodified": true,
+                "modified": false,
+                "parent": "trash",
+                "pinned": false,
+                "synced": true,
+                "type": "CollectionType",
+                "version": 3,
+                "visibleName": "Old"
+            }"#,
+        )
+        .unwrap();
+
+        assert!(meta.deleted && meta.metadata_modified && meta.synced && !meta.modified);
+        assert_eq!(meta.version, 3);
+        assert_eq!((meta.created, meta.last_opened), (None, None));
+        assert_eq!(meta.parent, Parent::Trash);
+    }
+
+    #[test]
+    fn reads_sample_content() {
+        let content: Content = serde_json::from_str(SAMPLE_CONTENT).unwrap();
+        let document = Document::from(content);
+
+        assert_eq!(document.format, Format::Notebook);
+        assert_eq!(document.page_count, Some(1));
+        assert_eq!(document.orientation, Some(Orientation::Portrait));
+        assert_eq!(document.size_in_bytes, Some(301971));
+        assert_eq!(document.cover_page, None);
+        assert_eq!(document.line_height, None);
+        assert_eq!(
+            document.extra_metadata.get("LastPen"),
+            Some(&Value::from("Highlighterv2"))
+        );
+
+        let [page] = &document.pages[..] else {
+            panic!("expected one page, got {:?}", document.pages);
+        };
+        assert_eq!(
+            page.id,
+            Uuid::from_str("2e9c7c50-6699-4686-8b53-c63e5b0d3cee").unwrap()
+        );
+        assert_eq!(page.idx.as_deref(), Some("ba"));
+        assert_eq!(page.template.as_deref(), Some("P Grid small"));
+        assert_eq!(page.redirect, None);
+    }
+
+    #[test]
+    fn keeps_unknown_fields() {
+        let content: Content = serde_json::from_str(SAMPLE_CONTENT).unwrap();
+        let written: Value = serde_json::to_value(&content).unwrap();
+        let original: Value = serde_json::from_str(SAMPLE_CONTENT).unwrap();
+
+        assert_eq!(written, original);
+    }
+
+    #[test]
+    fn reads_format_version_1() {
+        let content: Content = serde_json::from_str(
+            r#"{
+                "fileType": "pdf",
+                "formatVersion": 1,
+                "pages": [
+                    "00000000-0000-0000-0000-000000000001",
+                    "00000000-0000-0000-0000-000000000002"
+                ],
+                "redirectionPageMap": [0, -1],
+                "tags": [{ "name": "reading", "timestamp": 1711491490126 }],
+                "pageTags": [
+                    {
+                        "name": "todo",
+                        "pageId": "00000000-0000-0000-0000-000000000002",
+                        "timestamp": 1711491490126
+                    }
+                ],
+                "sizeInBytes": 1024
+            }"#,
+        )
+        .unwrap();
+        let document = Document::from(content);
+
+        let redirects: Vec<_> = document.pages.iter().map(|p| p.redirect).collect();
+        assert_eq!(redirects, [Some(0), None]);
+        assert_eq!(document.tags, ["reading"]);
+        assert_eq!(
+            document.page_tags,
+            [(document.pages[1].id, "todo".to_string())]
+        );
+        assert_eq!(document.size_in_bytes, Some(1024));
+    }
+
+    #[test]
+    fn skips_deleted_pages() {
+        let content: Content = serde_json::from_str(
+            r#"{
+                "fileType": "notebook",
+                "cPages": {
+                    "pages": [
+                        { "id": "00000000-0000-0000-0000-000000000001" },
+                        {
+                            "id": "00000000-0000-0000-0000-000000000002",
+                            "deleted": { "timestamp": "1:5", "value": 1 }
+                        }
+                    ]
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(Document::from(content).pages.len(), 1);
+    }
+
+    #[test]
+    fn orders_pages_by_index() {
+        let content: Content = serde_json::from_str(
+            r#"{
+                "fileType": "notebook",
+                "cPages": {
+                    "pages": [
+                        {
+                            "id": "00000000-0000-0000-0000-000000000002",
+                            "idx": { "timestamp": "1:4", "value": "bc" }
+                        },
+                        {
+                            "id": "00000000-0000-0000-0000-000000000001",
+                            "idx": { "timestamp": "1:2", "value": "ba" }
+                        },
+                        {
+                            "id": "00000000-0000-0000-0000-000000000003",
+                            "idx": { "timestamp": "1:6", "value": "bbn" }
+                        }
+                    ]
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let order: Vec<u128> = Document::from(content)
+            .pages
+            .iter()
+            .map(|p| p.id.as_u128())
+            .collect();
+        assert_eq!(order, [1, 3, 2]);
+    }
+}
@@ -0,0 +1,576 @@
+//! Typed representations of the blocks making up a v6 `.rm` file.
+
+use std::collections::HashMap;
+
+use color_eyre::eyre;
+use uuid::Uuid;
+
+use super::{
+    reader::{BlockInfo, TagType, TaggedReader},
+    CrdtId, LwwValue, Pen, PenColor,
+};
+
+pub const MIGRATION_INFO: u8 = 0x00;
+pub const SCENE_TREE: u8 = 0x01;
+pub const TREE_NODE: u8 = 0x02;
+pub const SCENE_GLYPH_ITEM: u8 = 0x03;
+pub const SCENE_GROUP_ITEM: u8 = 0x04;
+pub const SCENE_LINE_ITEM: u8 = 0x05;
+pub const SCENE_TEXT_ITEM: u8 = 0x06;
+pub const ROOT_TEXT: u8 = 0x07;
+pub const SCENE_TOMBSTONE_ITEM: u8 = 0x08;
+pub const AUTHOR_IDS: u8 = 0x09;
+pub const PAGE_INFO: u8 = 0x0A;
+pub const SCENE_INFO: u8 = 0x0D;
+
+/// A single top-level block of a v6 `.rm` file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    AuthorIds(AuthorIds),
+    MigrationInfo(MigrationInfo),
+    PageInfo(PageInfo),
+    SceneInfo(SceneInfo),
+    SceneTree(SceneTree),
+    TreeNode(TreeNode),
+    SceneGroupItem(SceneItem<CrdtId>),
+    SceneLineItem(SceneItem<Line>),
+    SceneGlyphItem(SceneItem<GlyphRange>),
+    /// Text items in the scene tree, whose payload isn't understood yet.
+    SceneTextItem(SceneItem<Vec<u8>>),
+    SceneTombstoneItem(SceneItem<()>),
+    RootText(RootText),
+    /// A block of an unknown type, or one that failed to parse.
+    Unreadable {
+        block_type: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl Block {
+    pub(super) fn from_reader(r: &mut TaggedReader, info: BlockInfo) -> eyre::Result<Self> {
+        Ok(match info.block_type {
+            AUTHOR_IDS => Self::AuthorIds(AuthorIds::from_reader(r)?),
+            MIGRATION_INFO => Self::MigrationInfo(MigrationInfo::from_reader(r)?),
+            PAGE_INFO => Self::PageInfo(PageInfo::from_reader(r)?),
+            SCENE_INFO => Self::SceneInfo(SceneInfo::from_reader(r)?),
+            SCENE_TREE => Self::SceneTree(SceneTree::from_reader(r)?),
+            TREE_NODE => Self::TreeNode(TreeNode::from_reader(r)?),
+            SCENE_GROUP_ITEM => Self::SceneGroupItem(SceneItem::from_reader(r, |r| r.read_id(2))?),
+            SCENE_LINE_ITEM => Self::SceneLineItem(SceneItem::from_reader(r, |r| {
+                Line::from_reader(r, info.current_version)
+            })?),
+            SCENE_GLYPH_ITEM => {
+                Self::SceneGlyphItem(SceneItem::from_reader(r, GlyphRange::from_reader)?)
+            }
+            SCENE_TEXT_ITEM => Self::SceneTextItem(SceneItem::from_reader(r, |r| {
+                Ok(r.read_bytes(r.remaining())?.to_vec())
+            })?),
+            SCENE_TOMBSTONE_ITEM => {
+                Self::SceneTombstoneItem(SceneItem::from_reader(r, |_| Ok(()))?)
+            }
+            ROOT_TEXT => Self::RootText(RootText::from_reader(r)?),
+            block_type => Self::Unreadable {
+                block_type,
+                data: r.read_bytes(r.remaining())?.to_vec(),
+            },
+        })
+    }
+}
+
+/// Maps the short author IDs used in [`CrdtId::part1`] to the UUIDs of the devices that made them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuthorIds(pub HashMap<u16, Uuid>);
+
+impl AuthorIds {
+    fn from_reader(r: &mut TaggedReader) -> eyre::Result<Self> {
+        let count = r.read_varuint()?;
+        let mut ids = HashMap::new();
+
+        for _ in 0..count {
+            r.read_subblock(0, |r| {
+                let length = r.read_varuint()? as usize;
+                let uuid = Uuid::from_slice(r.read_bytes(length)?)?;
+                let author = r.read_u16()?;
+
+                ids.insert(author, uuid);
+                Ok(())
+            })?;
+        }
+
+        Ok(Self(ids))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MigrationInfo {
+    pub migration_id: CrdtId,
+    pub is_device: bool,
+    pub unknown: Option<bool>,
+}
+
+impl MigrationInfo {
+    fn from_reader(r: &mut TaggedReader) -> eyre::Result<Self> {
+        Ok(Self {
+            migration_id: r.read_id(1)?,
+            is_device: r.read_bool(2)?,
+            unknown: match r.remaining() {
+                0 => None,
+                _ => Some(r.read_bool(3)?),
+            },
+        })
+    }
+}
+
+/// Statistics about how the page has been used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageInfo {
+    pub loads_count: u32,
+    pub merges_count: u32,
+    pub text_chars_count: u32,
+    pub text_lines_count: u32,
+    pub type_folio_use_count: u32,
+}
+
+impl PageInfo {
+    fn from_reader(r: &mut TaggedReader) -> eyre::Result<Self> {
+        Ok(Self {
+            loads_count: r.read_int(1)?,
+            merges_count: r.read_int(2)?,
+            text_chars_count: r.read_int(3)?,
+            text_lines_count: r.read_int(4)?,
+            type_folio_use_count: match r.remaining() {
+                0 => 0,
+                _ => r.read_int(5)?,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneInfo {
+    pub current_layer: LwwValue<CrdtId>,
+    pub background_visible: Option<LwwValue<bool>>,
+    pub root_document_visible: Option<LwwValue<bool>>,
+    pub paper_size: Option<(u32, u32)>,
+}
+
+impl SceneInfo {
+    fn from_reader(r: &mut TaggedReader) -> eyre::Result<Self> {
+        let current_layer = r.read_lww_id(1)?;
+
+        let background_visible = match r.has_subblock(2) {
+            true => Some(r.read_lww_bool(2)?),
+            false => None,
+        };
+
+        let root_document_visible = match r.has_subblock(3) {
+            true => Some(r.read_lww_bool(3)?),
+            false => None,
+        };
+
+        let paper_size = match r.has_subblock(5) {
+            true => Some(r.read_int_pair(5)?),
+            false => None,
+        };
+
+        Ok(Self {
+            current_layer,
+            background_visible,
+            root_document_visible,
+            paper_size,
+        })
+    }
+}
+
+/// Declares that the node `tree_id` lives under the node `parent_id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SceneTree {
+    pub tree_id: CrdtId,
+    pub node_id: CrdtId,
+    pub is_update: bool,
+    pub parent_id: CrdtId,
+}
+
+impl SceneTree {
+    fn from_reader(r: &mut TaggedReader) -> eyre::Result<Self> {
+        Ok(Self {
+            tree_id: r.read_id(1)?,
+            node_id: r.read_id(2)?,
+            is_update: r.read_bool(3)?,
+            parent_id: r.read_subblock(4, |r| r.read_id(1))?,
+        })
+    }
+}
+
+/// Properties of a group (layer) in the scene tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeNode {
+    pub node_id: CrdtId,
+    pub label: LwwValue<String>,
+    pub visible: LwwValue<bool>,
+    /// Present on groups that are anchored to a position in the page's text.
+    pub anchor: Option<Anchor>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anchor {
+    pub id: LwwValue<CrdtId>,
+    pub kind: LwwValue<u8>,
+    pub threshold: LwwValue<f32>,
+    pub origin_x: LwwValue<f32>,
+}
+
+impl TreeNode {
+    fn from_reader(r: &mut TaggedReader) -> eyre::Result<Self> {
+        let node_id = r.read_id(1)?;
+        let label = r.read_lww_string(2)?;
+        let visible = r.read_lww_bool(3)?;
+
+        let anchor = match r.has_subblock(7) {
+            true => Some(Anchor {
+                id: r.read_lww_id(7)?,
+                kind: r.read_lww_byte(8)?,
+                threshold: r.read_lww_float(9)?,
+                origin_x: r.read_lww_float(10)?,
+            }),
+            false => None,
+        };
+
+        Ok(Self {
+            node_id,
+            label,
+            visible,
+            anchor,
+        })
+    }
+}
+
+/// An entry in one of the CRDT sequences making up the scene tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneItem<T> {
+    pub parent_id: CrdtId,
+    pub item_id: CrdtId,
+    pub left_id: CrdtId,
+    pub right_id: CrdtId,
+    pub deleted_length: u32,
+    /// `None` for deleted items.
+    pub value: Option<T>,
+}
+
+impl<T> SceneItem<T> {
+    fn from_reader(
+        r: &mut TaggedReader,
+        value: impl FnOnce(&mut TaggedReader) -> eyre::Result<T>,
+    ) -> eyre::Result<Self> {
+        let parent_id = r.read_id(1)?;
+        let item_id = r.read_id(2)?;
+        let left_id = r.read_id(3)?;
+        let right_id = r.read_id(4)?;
+        let deleted_length = r.read_int(5)?;
+
+        let value = match r.has_subblock(6) {
+            true => Some(r.read_subblock(6, |r| {
+                let _item_type = r.read_u8()?;
+                value(r)
+            })?),
+            false => None,
+        };
+
+        Ok(Self {
+            parent_id,
+            item_id,
+            left_id,
+            right_id,
+            deleted_length,
+            value,
+        })
+    }
+}
+
+/// A single pen stroke.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub tool: Pen,
+    pub color: PenColor,
+    pub thickness_scale: f64,
+    pub starting_length: f32,
+    pub points: Vec<Point>,
+    pub timestamp: CrdtId,
+    pub move_id: Option<CrdtId>,
+}
+
+impl Line {
+    fn from_reader(r: &mut TaggedReader, version: u8) -> eyre::Result<Self> {
+        let tool = Pen::from(r.read_int(1)?);
+        let color = PenColor::from(r.read_int(2)?);
+        let thickness_scale = r.read_double(3)?;
+        let starting_length = r.read_float(4)?;
+
+        let points = r.read_subblock(5, |r| {
+            let point_size = match version {
+                1 => 0x18,
+                _ => 0x0E,
+            };
+
+            let count = r.remaining() / point_size;
+            (0..count).map(|_| Point::from_reader(r, version)).collect()
+        })?;
+
+        let timestamp = r.read_id(6)?;
+        let move_id = match r.remaining() {
+            0 => None,
+            _ => Some(r.read_id(7)?),
+        };
+
+        Ok(Self {
+            tool,
+            color,
+            thickness_scale,
+            starting_length,
+            points,
+            timestamp,
+            move_id,
+        })
+    }
+}
+
+/// A point in a [`Line`], in the page's coordinate space (origin at the top center).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+    pub speed: u16,
+    pub direction: u8,
+    pub width: u16,
+    pub pressure: u8,
+}
+
+impl Point {
+    /// Build a point from unquantized values, as stored by older formats.
+    pub fn from_floats(
+        x: f32,
+        y: f32,
+        speed: f32,
+        direction: f32,
+        width: f32,
+        pressure: f32,
+    ) -> Self {
+        Self {
+            x,
+            y,
+            speed: (speed * 4.0).round() as u16,
+            direction: (255.0 * direction / std::f32::consts::TAU).round() as u8,
+            width: (width * 4.0).round() as u16,
+            pressure: (pressure * 255.0).round() as u8,
+        }
+    }
+
+    fn from_reader(r: &mut TaggedReader, version: u8) -> eyre::Result<Self> {
+        let x = r.read_f32()?;
+        let y = r.read_f32()?;
+
+        // version 1 stores everything as floats, which were later quantized
+        if version == 1 {
+            return Ok(Self::from_floats(
+                x,
+                y,
+                r.read_f32()?,
+                r.read_f32()?,
+                r.read_f32()?,
+                r.read_f32()?,
+            ));
+        }
+
+        Ok(Self {
+            x,
+            y,
+            speed: r.read_u16()?,
+            width: r.read_u16()?,
+            direction: r.read_u8()?,
+            pressure: r.read_u8()?,
+        })
+    }
+}
+
+/// A highlighted range of text in a PDF or EPUB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphRange {
+    pub start: Option<u32>,
+    pub length: u32,
+    pub color: PenColor,
+    pub text: String,
+    pub rects: Vec<Rect>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl GlyphRange {
+    fn from_reader(r: &mut TaggedReader) -> eyre::Result<Self> {
+        let start = match r.check_tag(2, TagType::Byte4) {
+            true => Some(r.read_int(2)?),
+            false => None,
+        };
+        let length = r.read_int(3)?;
+        let color = PenColor::from(r.read_int(4)?);
+        let text = r.read_string(5)?;
+
+        let rects = r.read_subblock(6, |r| {
+            let count = r.read_varuint()?;
+
+            (0..count)
+                .map(|_| {
+                    Ok(Rect {
+                        x: r.read_f64()?,
+                        y: r.read_f64()?,
+                        w: r.read_f64()?,
+                        h: r.read_f64()?,
+                    })
+                })
+                .collect()
+        })?;
+
+        Ok(Self {
+            start,
+            length,
+            color,
+            text,
+            rects,
+        })
+    }
+}
+
+/// The typed text of a page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootText {
+    pub block_id: CrdtId,
+    pub items: Vec<TextItem>,
+    /// Paragraph styles, keyed by the character at the start of each paragraph.
+    pub styles: Vec<(CrdtId, LwwValue<ParagraphStyle>)>,
+    pub pos_x: f64,
+    pub pos_y: f64,
+    pub width: f32,
+}
+
+/// A run of characters (or a formatting marker) in a [`RootText`]'s CRDT sequence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextItem {
+    pub item_id: CrdtId,
+    pub left_id: CrdtId,
+    pub right_id: CrdtId,
+    pub deleted_length: u32,
+    pub text: String,
+    pub format: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParagraphStyle {
+    Basic,
+    Plain,
+    Heading,
+    Bold,
+    Bullet,
+    Bullet2,
+    Checkbox,
+    CheckboxChecked,
+    Unknown(u8),
+}
+
+impl From<u8> for ParagraphStyle {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Basic,
+            1 => Self::Plain,
+            2 => Self::Heading,
+            3 => Self::Bold,
+            4 => Self::Bullet,
+            5 => Self::Bullet2,
+            6 => Self::Checkbox,
+            7 => Self::CheckboxChecked,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+impl RootText {
+    fn from_reader(r: &mut TaggedReader) -> eyre::Result<Self> {
+        let block_id = r.read_id(1)?;
+
+        let (items, styles) = r.read_subblock(2, |r| {
+            let items = r.read_subblock(1, |r| r.read_subblock(1, Self::read_items))?;
+            let styles = r.read_subblock(2, |r| r.read_subblock(1, Self::read_styles))?;
+
+            Ok((items, styles))
+        })?;
+
+        let (pos_x, pos_y) = r.read_subblock(3, |r| Ok((r.read_f64()?, r.read_f64()?)))?;
+        let width = r.read_float(4)?;
+
+        Ok(Self {
+            block_id,
+            items,
+            styles,
+            pos_x,
+            pos_y,
+            width,
+        })
+    }
+
+    fn read_items(r: &mut TaggedReader) -> eyre::Result<Vec<TextItem>> {
+        let count = r.read_varuint()?;
+
+        (0..count)
+            .map(|_| {
+                r.read_subblock(0, |r| {
+                    let item_id = r.read_id(2)?;
+                    let left_id = r.read_id(3)?;
+                    let right_id = r.read_id(4)?;
+                    let deleted_length = r.read_int(5)?;
+
+                    let (text, format) = match r.has_subblock(6) {
+                        true => r.read_string_with_format(6)?,
+                        false => (String::new(), None),
+                    };
+
+                    Ok(TextItem {
+                        item_id,
+                        left_id,
+                        right_id,
+                        deleted_length,
+                        text,
+                        format,
+                    })
+                })
+            })
+            .collect()
+    }
+
+    fn read_styles(r: &mut TaggedReader) -> eyre::Result<Vec<(CrdtId, LwwValue<ParagraphStyle>)>> {
+        let count = r.read_varuint()?;
+
+        (0..count)
+            .map(|_| {
+                // character ids here aren't tagged like the rest
+                let char_id = r.read_crdt_id()?;
+                let timestamp = r.read_id(1)?;
+                let style = r.read_subblock(2, |r| {
+                    let _marker = r.read_u8()?;
+                    Ok(ParagraphStyle::from(r.read_u8()?))
+                })?;
+
+                Ok((
+                    char_id,
+                    LwwValue {
+                        timestamp,
+                        value: style,
+                    },
+                ))
+            })
+            .collect()
+    }
+}
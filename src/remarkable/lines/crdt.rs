@@ -0,0 +1,165 @@
+//! Resolution of the CRDT sequences in v6 files into their final order.
+//!
+//! Every item in a sequence records the IDs of its left and right neighbours at the time it
+//! was inserted, with [`END_MARKER`] standing in for either end of the sequence. Sorting the
+//! items topologically by those constraints gives the order shown on the device.
+
+use std::{collections::HashMap, str::FromStr};
+
+use color_eyre::eyre;
+
+use super::{CrdtId, LwwValue, SceneItem, TextItem};
+
+/// Marks the start (as a left neighbour) or end (as a right neighbour) of a sequence.
+pub const END_MARKER: CrdtId = CrdtId::new(0, 0);
+
+/// An element of a CRDT sequence.
+pub trait SequenceItem {
+    fn item_id(&self) -> CrdtId;
+    fn left_id(&self) -> CrdtId;
+    fn right_id(&self) -> CrdtId;
+    /// Whether the item has been deleted and only remains as a tombstone.
+    fn is_deleted(&self) -> bool;
+}
+
+impl<T> SequenceItem for SceneItem<T> {
+    fn item_id(&self) -> CrdtId {
+        self.item_id
+    }
+
+    fn left_id(&self) -> CrdtId {
+        self.left_id
+    }
+
+    fn right_id(&self) -> CrdtId {
+        self.right_id
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.value.is_none()
+    }
+}
+
+impl SequenceItem for TextItem {
+    fn item_id(&self) -> CrdtId {
+        self.item_id
+    }
+
+    fn left_id(&self) -> CrdtId {
+        self.left_id
+    }
+
+    fn right_id(&self) -> CrdtId {
+        self.right_id
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.deleted_length > 0 && self.text.is_empty()
+    }
+}
+
+/// A node in the ordering graph, with sentinels for both ends of the sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Node {
+    Start,
+    Item(CrdtId),
+    End,
+}
+
+/// Order a sequence's items, dropping tombstones.
+///
+/// Items sharing an ID are treated as updates, and the last one wins. Items whose
+/// constraints can't be satisfied (a cycle) are appended in ID order rather than lost.
+pub fn resolve<I: SequenceItem>(items: impl IntoIterator<Item = I>) -> Vec<I> {
+    let mut by_id: HashMap<CrdtId, I> = HashMap::new();
+    for item in items {
+        by_id.insert(item.item_id(), item);
+    }
+
+    // every node maps to the nodes that must come after it, and counts those before it
+    let mut next: HashMap<Node, Vec<Node>> = HashMap::new();
+    let mut before: HashMap<Node, usize> = HashMap::new();
+    for item in by_id.values() {
+        let left = match item.left_id() {
+            END_MARKER => Node::Start,
+            id => Node::Item(id),
+        };
+        let right = match item.right_id() {
+            END_MARKER => Node::End,
+            id => Node::Item(id),
+        };
+        let node = Node::Item(item.item_id());
+
+        for (first, then) in [(left, node), (node, right)] {
+            next.entry(first).or_default().push(then);
+            before.entry(first).or_default();
+            *before.entry(then).or_default() += 1;
+        }
+    }
+
+    // taken a layer at a time, in ID order within each, so ties resolve like the device
+    let mut ready: Vec<Node> = before
+        .iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(node, _)| *node)
+        .collect();
+    let mut order = Vec::with_capacity(by_id.len());
+    while !ready.is_empty() {
+        ready.sort_unstable();
+
+        let mut unblocked = Vec::new();
+        for node in ready {
+            if let Node::Item(id) = node {
+                if let Some(item) = by_id.remove(&id) {
+                    order.push(item);
+                }
+            }
+
+            for then in next.remove(&node).unwrap_or_default() {
+                let count = before.entry(then).or_default();
+                *count -= 1;
+                if *count == 0 {
+                    unblocked.push(then);
+                }
+            }
+        }
+        ready = unblocked;
+    }
+
+    if !by_id.is_empty() {
+        tracing::warn!("{} CRDT items form a cycle", by_id.len());
+
+        let mut rest: Vec<I> = by_id.into_values().collect();
+        rest.sort_by_key(|i| i.item_id());
+        order.extend(rest);
+    }
+
+    order.retain(|item| !item.is_deleted());
+    order
+}
+
+impl<T> LwwValue<T> {
+    /// Keep whichever of the two values was written last.
+    pub fn merge(self, other: Self) -> Self {
+        match other.timestamp >= self.timestamp {
+            true => other,
+            false => self,
+        }
+    }
+}
+
+/// Parses the `"<author>:<counter>"` form used for timestamps in `.content` files.
+impl FromStr for CrdtId {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((part1, part2)) = s.split_once(':') else {
+            return Err(eyre::eyre!("invalid CRDT id {s:?}"));
+        };
+
+        Ok(Self {
+            part1: part1.parse()?,
+            part2: part2.parse()?,
+        })
+    }
+}
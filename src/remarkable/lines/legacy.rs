@@ -0,0 +1,76 @@
+//! Parsing for the pre-3.0 (v3 and v5) `.rm` formats.
+//!
+//! These are a flat list of layers, each holding a list of strokes made up of segments.
+//! See [Axel Huebl's write-up](https://plasma.ninja/blog/devices/remarkable/binary/format/2017/12/26/reMarkable-lines-file-format.html).
+
+use color_eyre::eyre;
+
+use super::{reader::TaggedReader, CrdtId, Layer, Line, Page, Pen, PenColor, Point, PAGE_WIDTH};
+
+/// Parse the body of a v3 or v5 `.rm` file, after its header.
+pub fn read_page(body: &[u8], version: u8) -> eyre::Result<Page> {
+    let mut r = TaggedReader::new(body);
+
+    let layer_count = r.read_u32()?;
+    let layers = (0..layer_count)
+        .map(|i| {
+            let line_count = r.read_u32()?;
+            let lines = (0..line_count)
+                .map(|_| read_line(&mut r, version))
+                .collect::<eyre::Result<_>>()?;
+
+            Ok(Layer {
+                id: CrdtId::new(0, u64::from(i)),
+                name: format!("Layer {}", i + 1),
+                visible: true,
+                lines,
+                glyphs: Vec::new(),
+            })
+        })
+        .collect::<eyre::Result<_>>()?;
+
+    Ok(Page {
+        version,
+        layers,
+        ..Default::default()
+    })
+}
+
+fn read_line(r: &mut TaggedReader, version: u8) -> eyre::Result<Line> {
+    let tool = Pen::from(r.read_u32()?);
+    let color = PenColor::from(r.read_u32()?);
+    let _unknown = r.read_u32()?;
+    let base_width = r.read_f32()?;
+
+    // v5 added another field before the segments
+    if version >= 5 {
+        let _unknown = r.read_u32()?;
+    }
+
+    let segment_count = r.read_u32()?;
+    let points = (0..segment_count)
+        .map(|_| read_segment(r))
+        .collect::<eyre::Result<_>>()?;
+
+    Ok(Line {
+        tool,
+        color,
+        thickness_scale: f64::from(base_width),
+        starting_length: 0.0,
+        points,
+        timestamp: CrdtId::default(),
+        move_id: None,
+    })
+}
+
+/// Read a segment, moving its origin from the top left to the top center like in v6.
+fn read_segment(r: &mut TaggedReader) -> eyre::Result<Point> {
+    Ok(Point::from_floats(
+        r.read_f32()? - PAGE_WIDTH / 2.0,
+        r.read_f32()?,
+        r.read_f32()?,
+        r.read_f32()?,
+        r.read_f32()?,
+        r.read_f32()?,
+    ))
+}
@@ -0,0 +1,461 @@
+//! Parsing for the `.rm` ("lines") files which store the contents of each page.
+//!
+//! Since software version 3.0, pages are stored in the v6 format: a header followed by
+//! a sequence of tagged blocks describing a CRDT scene tree. See
+//! [rmscene](https://github.com/ricklupton/rmscene) for the reference implementation.
+//! Older pages use the much simpler v3 and v5 formats, see [`legacy`].
+//!
+//! Every version is read into the same [`Page`] model.
+
+use std::path::Path;
+
+use color_eyre::eyre;
+use tokio::fs;
+use uuid::Uuid;
+
+mod blocks;
+pub mod crdt;
+mod legacy;
+mod reader;
+mod scene;
+
+pub use blocks::*;
+pub use scene::*;
+
+use reader::TaggedReader;
+
+pub const LINES_EXTENSION: &str = "rm";
+
+/// Every `.rm` file starts with this, followed by the version and padded with spaces.
+const HEADER_PREFIX: &[u8] = b"reMarkable .lines file, version=";
+const HEADER_LENGTH: usize = 43;
+
+/// Size of the page canvas in `.rm` units (pixels on the device).
+pub const PAGE_WIDTH: f32 = 1404.0;
+pub const PAGE_HEIGHT: f32 = 1872.0;
+
+/// Detect the format version from the header of a `.rm` file.
+pub fn version(data: &[u8]) -> Option<u8> {
+    let header = data.get(..HEADER_LENGTH)?;
+    let version = header.strip_prefix(HEADER_PREFIX)?;
+
+    std::str::from_utf8(version).ok()?.trim_end().parse().ok()
+}
+
+/// Parse the blocks of a v6 `.rm` file.
+pub fn read_blocks(data: &[u8]) -> eyre::Result<Vec<Block>> {
+    match version(data) {
+        Some(6) => (),
+        Some(v) => return Err(eyre::eyre!("unsupported .rm version {v}")),
+        None => return Err(eyre::eyre!("invalid .rm header")),
+    }
+
+    let mut r = TaggedReader::new(&data[HEADER_LENGTH..]);
+    let mut blocks = Vec::new();
+
+    while r.remaining() > 0 {
+        let block = r.read_block(|r, info| {
+            let start = r.position();
+
+            Ok(Block::from_reader(r, info).unwrap_or_else(|err| {
+                tracing::warn!(
+                    "failed to parse block of type {:#x}: {err}",
+                    info.block_type
+                );
+
+                Block::Unreadable {
+                    block_type: info.block_type,
+                    data: data[HEADER_LENGTH + start..HEADER_LENGTH + start + info.size].to_vec(),
+                }
+            }))
+        })?;
+
+        blocks.push(block);
+    }
+
+    Ok(blocks)
+}
+
+/// Parse a `.rm` file of any supported version.
+pub fn read(data: &[u8]) -> eyre::Result<Page> {
+    match version(data) {
+        Some(6) => Ok(Page::from_blocks(read_blocks(data)?)),
+        Some(v @ (3 | 5)) => legacy::read_page(&data[HEADER_LENGTH..], v),
+        Some(v) => Err(eyre::eyre!("unsupported .rm version {v}")),
+        None => Err(eyre::eyre!("invalid .rm header")),
+    }
+}
+
+/// Read \<BASE\>/\<DOCUMENT\>/\<PAGE\>.rm
+///
+/// Pages that were never written on have no file, and are read as empty.
+pub async fn read_page(base: &Path, document: &Uuid, page: &Uuid) -> eyre::Result<Page> {
+    let mut path = base.join(document.to_string()).join(page.to_string());
+    path.set_extension(LINES_EXTENSION);
+
+    if !path.exists() {
+        return Ok(Page::default());
+    }
+
+    read(&fs::read(path).await?)
+}
+
+/// A unique identifier in the CRDT, made up of an author ID and a per-author counter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CrdtId {
+    pub part1: u8,
+    pub part2: u64,
+}
+
+impl CrdtId {
+    pub const fn new(part1: u8, part2: u64) -> Self {
+        Self { part1, part2 }
+    }
+}
+
+impl std::fmt::Display for CrdtId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.part1, self.part2)
+    }
+}
+
+/// A last-write-wins register: the value with the greatest timestamp is the current one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LwwValue<T> {
+    pub timestamp: CrdtId,
+    pub value: T,
+}
+
+/// The tool a [`Line`] was drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pen {
+    Paintbrush1,
+    Pencil1,
+    Ballpoint1,
+    Marker1,
+    Fineliner1,
+    Highlighter1,
+    Eraser,
+    MechanicalPencil1,
+    EraserArea,
+    Paintbrush2,
+    MechanicalPencil2,
+    Pencil2,
+    Ballpoint2,
+    Marker2,
+    Fineliner2,
+    Highlighter2,
+    Calligraphy,
+    Shader,
+    Unknown(u32),
+}
+
+impl From<u32> for Pen {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => Self::Paintbrush1,
+            1 => Self::Pencil1,
+            2 => Self::Ballpoint1,
+            3 => Self::Marker1,
+            4 => Self::Fineliner1,
+            5 => Self::Highlighter1,
+            6 => Self::Eraser,
+            7 => Self::MechanicalPencil1,
+            8 => Self::EraserArea,
+            12 => Self::Paintbrush2,
+            13 => Self::MechanicalPencil2,
+            14 => Self::Pencil2,
+            15 => Self::Ballpoint2,
+            16 => Self::Marker2,
+            17 => Self::Fineliner2,
+            18 => Self::Highlighter2,
+            21 => Self::Calligraphy,
+            23 => Self::Shader,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+/// The color of a [`Line`] or [`GlyphRange`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PenColor {
+    Black,
+    Gray,
+    White,
+    Yellow,
+    Green,
+    Pink,
+    Blue,
+    Red,
+    GrayOverlap,
+    Highlight,
+    Green2,
+    Cyan,
+    Magenta,
+    Yellow2,
+    Unknown(u32),
+}
+
+impl From<u32> for PenColor {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => Self::Black,
+            1 => Self::Gray,
+            2 => Self::White,
+            3 => Self::Yellow,
+            4 => Self::Green,
+            5 => Self::Pink,
+            6 => Self::Blue,
+            7 => Self::Red,
+            8 => Self::GrayOverlap,
+            9 => Self::Highlight,
+            10 => Self::Green2,
+            11 => Self::Cyan,
+            12 => Self::Magenta,
+            13 => Self::Yellow2,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_PAGE: &[u8] = include_bytes!(
+        "../../../samples/v6/1dc81a48-ecf8-4c11-a3e4-65dda27270a3/2e9c7c50-6699-4686-8b53-c63e5b0d3cee.rm"
+    );
+
+    fn sample_blocks() -> Vec<Block> {
+        read_blocks(SAMPLE_PAGE).expect("sample page should parse")
+    }
+
+    #[test]
+    fn detects_version() {
+        assert_eq!(version(SAMPLE_PAGE), Some(6));
+        assert_eq!(version(b"not a lines file"), None);
+    }
+
+    #[test]
+    fn parses_every_block() {
+        let blocks = sample_blocks();
+
+        assert_eq!(blocks.len(), 207);
+        assert!(!blocks.iter().any(|b| matches!(b, Block::Unreadable { .. })));
+    }
+
+    #[test]
+    fn parses_header_blocks() {
+        let blocks = sample_blocks();
+
+        let Block::AuthorIds(AuthorIds(authors)) = &blocks[0] else {
+            panic!("expected author ids, found {:?}", blocks[0]);
+        };
+        assert_eq!(
+            authors.get(&1),
+            Some(&Uuid::parse_str("907e4928-6e02-5e26-936c-426ab0841070").unwrap())
+        );
+
+        assert_eq!(
+            blocks[1],
+            Block::MigrationInfo(MigrationInfo {
+                migration_id: CrdtId::new(1, 1),
+                is_device: true,
+                unknown: Some(false),
+            })
+        );
+
+        assert_eq!(
+            blocks[2],
+            Block::PageInfo(PageInfo {
+                loads_count: 2,
+                merges_count: 0,
+                text_chars_count: 33,
+                text_lines_count: 6,
+                type_folio_use_count: 0,
+            })
+        );
+
+        let Block::SceneInfo(info) = &blocks[3] else {
+            panic!("expected scene info, found {:?}", blocks[3]);
+        };
+        assert_eq!(info.current_layer.value, CrdtId::new(0, 0));
+        assert_eq!(info.paper_size, None);
+    }
+
+    #[test]
+    fn parses_tree() {
+        let blocks = sample_blocks();
+
+        let layer = blocks
+            .iter()
+            .find_map(|b| match b {
+                Block::TreeNode(node) if node.node_id == CrdtId::new(0, 11) => Some(node),
+                _ => None,
+            })
+            .expect("sample page has a layer");
+        assert_eq!(layer.label.value, "Layer 1");
+        assert!(layer.visible.value);
+        assert!(layer.anchor.is_none());
+
+        let anchored = blocks
+            .iter()
+            .filter(|b| {
+                matches!(
+                    b,
+                    Block::TreeNode(TreeNode {
+                        anchor: Some(_),
+                        ..
+                    })
+                )
+            })
+            .count();
+        assert_eq!(anchored, 11);
+
+        let trees = blocks
+            .iter()
+            .filter(|b| matches!(b, Block::SceneTree(_)))
+            .count();
+        assert_eq!(trees, 21);
+    }
+
+    #[test]
+    fn parses_lines() {
+        let blocks = sample_blocks();
+
+        let lines: Vec<&SceneItem<Line>> = blocks
+            .iter()
+            .filter_map(|b| match b {
+                Block::SceneLineItem(item) => Some(item),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(lines.len(), 156);
+
+        // the first stroke was erased
+        assert_eq!(lines[0].deleted_length, 0x43);
+        assert!(lines[0].value.is_none());
+
+        let line = lines
+            .iter()
+            .find_map(|l| l.value.as_ref())
+            .expect("sample page has strokes");
+        assert_eq!(line.tool, Pen::Pencil2);
+        assert_eq!(line.color, PenColor::Black);
+        assert_eq!(line.thickness_scale, 1.0);
+        assert_eq!(line.points.len(), 2);
+        assert_eq!(line.points[0].width, 8);
+        assert_eq!(line.timestamp, CrdtId::new(1, 482));
+        assert_eq!(line.move_id, Some(CrdtId::new(1, 38)));
+    }
+
+    #[test]
+    fn parses_root_text() {
+        let blocks = sample_blocks();
+
+        let text = blocks
+            .iter()
+            .find_map(|b| match b {
+                Block::RootText(text) => Some(text),
+                _ => None,
+            })
+            .expect("sample page has text");
+
+        assert_eq!(text.items.len(), 6);
+        assert_eq!(text.items[0].text, "Large\n");
+        assert_eq!(text.styles.len(), 4);
+        assert_eq!(text.pos_x, -468.0);
+        assert_eq!(text.pos_y, 234.0);
+        assert_eq!(text.width, 936.0);
+    }
+
+    #[test]
+    fn builds_v6_page() {
+        let page = read(SAMPLE_PAGE).unwrap();
+
+        assert_eq!(page.version, 6);
+        assert_eq!(page.layers.len(), 1);
+        assert_eq!(page.layers[0].name, "Layer 1");
+        assert!(page.visible_lines().count() > 0);
+        let text = page.text.expect("sample page has text");
+        assert_eq!(text.plain_text(), "Large\nmedium\nsmall\nbullet\ncheck\n");
+    }
+
+    #[test]
+    fn resolves_crdt_sequences() {
+        let item = |id: u64, left: u64, right: u64, value: Option<u32>| SceneItem {
+            parent_id: ROOT_NODE,
+            item_id: CrdtId::new(1, id),
+            left_id: CrdtId::new(1, left),
+            right_id: CrdtId::new(1, right),
+            deleted_length: u32::from(value.is_none()),
+            value,
+        };
+
+        // 3 was inserted between 1 and 2, 4 at the end, then 2 was deleted
+        let items = vec![
+            item(4, 2, 0, Some(4)),
+            item(2, 1, 0, None),
+            item(1, 0, 0, Some(1)),
+            item(3, 1, 2, Some(3)),
+        ];
+
+        let values: Vec<u32> = crdt::resolve(items)
+            .into_iter()
+            .filter_map(|i| i.value)
+            .collect();
+        assert_eq!(values, [1, 3, 4]);
+
+        // typed one after the other, as on a page full of text
+        let count = 50_000;
+        let items = (1..=count)
+            .rev()
+            .map(|id| item(id, id - 1, 0, Some(id as u32)));
+        let values: Vec<u32> = crdt::resolve(items)
+            .into_iter()
+            .filter_map(|i| i.value)
+            .collect();
+        assert_eq!(values, (1..=count as u32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reads_legacy_pages() {
+        fn header(version: u8) -> Vec<u8> {
+            format!("reMarkable .lines file, version={version:<11}").into_bytes()
+        }
+
+        for version in [3, 5] {
+            let mut data = header(version);
+            let push = |data: &mut Vec<u8>, v: u32| data.extend_from_slice(&v.to_le_bytes());
+
+            // one layer with one stroke of two segments
+            push(&mut data, 1);
+            push(&mut data, 1);
+            push(&mut data, 15);
+            push(&mut data, 0);
+            push(&mut data, 0);
+            push(&mut data, 2.0f32.to_bits());
+            if version == 5 {
+                push(&mut data, 0);
+            }
+            push(&mut data, 2);
+            for x in [702.0f32, 800.0] {
+                for v in [x, 100.0, 0.5, 0.0, 2.0, 1.0] {
+                    push(&mut data, v.to_bits());
+                }
+            }
+
+            let page = read(&data).unwrap();
+            assert_eq!(page.version, version);
+            assert_eq!(page.layers.len(), 1);
+
+            let line = &page.layers[0].lines[0];
+            assert_eq!(line.tool, Pen::Ballpoint2);
+            assert_eq!(line.points.len(), 2);
+            assert_eq!(line.points[0].x, 0.0);
+            assert_eq!(line.points[1].x, 98.0);
+            assert_eq!(line.points[0].width, 8);
+            assert_eq!(line.points[0].pressure, 255);
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! Low-level reader for the tagged binary encoding used inside v6 `.rm` blocks.
+
+use color_eyre::eyre;
+
+use super::{CrdtId, LwwValue};
+
+/// The type nibble of a tag, describing what kind of value follows it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagType {
+    Id = 0xF,
+    Length4 = 0xC,
+    Byte8 = 0x8,
+    Byte4 = 0x4,
+    Byte1 = 0x1,
+}
+
+/// Header information shared by every top-level block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub offset: usize,
+    pub size: usize,
+    pub block_type: u8,
+    pub min_version: u8,
+    pub current_version: u8,
+}
+
+/// A cursor over a `.rm` file which keeps track of (nested) block boundaries.
+pub struct TaggedReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// End offsets of the blocks and sub-blocks currently being read, innermost last.
+    ends: Vec<usize>,
+}
+
+impl<'a> TaggedReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            ends: Vec::new(),
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// The end of the innermost block being read, or the end of the data.
+    fn end(&self) -> usize {
+        self.ends.last().copied().unwrap_or(self.data.len())
+    }
+
+    /// Bytes left before the end of the current (sub-)block.
+    pub fn remaining(&self) -> usize {
+        self.end().saturating_sub(self.pos)
+    }
+
+    pub fn read_bytes(&mut self, n: usize) -> eyre::Result<&'a [u8]> {
+        if n > self.remaining() {
+            return Err(eyre::eyre!(
+                "unexpected end of block reading {n} bytes at offset {}",
+                self.pos
+            ));
+        }
+
+        let bytes = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> eyre::Result<[u8; N]> {
+        Ok(self.read_bytes(N)?.try_into()?)
+    }
+
+    pub fn read_u8(&mut self) -> eyre::Result<u8> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub fn read_u16(&mut self) -> eyre::Result<u16> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> eyre::Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_f32(&mut self) -> eyre::Result<f32> {
+        Ok(f32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_f64(&mut self) -> eyre::Result<f64> {
+        Ok(f64::from_le_bytes(self.read_array()?))
+    }
+
+    /// Read a LEB128-style variable length unsigned integer.
+    pub fn read_varuint(&mut self) -> eyre::Result<u64> {
+        let mut result = 0u64;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.read_u8()?;
+
+            if shift < 64 {
+                result |= u64::from(byte & 0x7F) << shift;
+            }
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Read a [`CrdtId`] without a preceding tag.
+    pub fn read_crdt_id(&mut self) -> eyre::Result<CrdtId> {
+        Ok(CrdtId {
+            part1: self.read_u8()?,
+            part2: self.read_varuint()?,
+        })
+    }
+
+    /// Read the next tag, returning its index and type.
+    fn read_raw_tag(&mut self) -> eyre::Result<(u64, u8)> {
+        let tag = self.read_varuint()?;
+        Ok((tag >> 4, (tag & 0xF) as u8))
+    }
+
+    /// Check whether the next tag matches `index` and `kind` without consuming it.
+    pub fn check_tag(&mut self, index: u64, kind: TagType) -> bool {
+        if self.remaining() == 0 {
+            return false;
+        }
+
+        let pos = self.pos;
+        let matches = self
+            .read_raw_tag()
+            .is_ok_and(|(i, k)| i == index && k == kind as u8);
+        self.pos = pos;
+
+        matches
+    }
+
+    pub fn read_tag(&mut self, index: u64, kind: TagType) -> eyre::Result<()> {
+        let offset = self.pos;
+        let (i, k) = self.read_raw_tag()?;
+
+        if i != index || k != kind as u8 {
+            self.pos = offset;
+            return Err(eyre::eyre!(
+                "expected tag ({index}, {kind:?}) at offset {offset}, found ({i}, {k:#x})"
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn read_id(&mut self, index: u64) -> eyre::Result<CrdtId> {
+        self.read_tag(index, TagType::Id)?;
+        self.read_crdt_id()
+    }
+
+    pub fn read_bool(&mut self, index: u64) -> eyre::Result<bool> {
+        self.read_tag(index, TagType::Byte1)?;
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_byte(&mut self, index: u64) -> eyre::Result<u8> {
+        self.read_tag(index, TagType::Byte1)?;
+        self.read_u8()
+    }
+
+    pub fn read_int(&mut self, index: u64) -> eyre::Result<u32> {
+        self.read_tag(index, TagType::Byte4)?;
+        self.read_u32()
+    }
+
+    pub fn read_float(&mut self, index: u64) -> eyre::Result<f32> {
+        self.read_tag(index, TagType::Byte4)?;
+        self.read_f32()
+    }
+
+    pub fn read_double(&mut self, index: u64) -> eyre::Result<f64> {
+        self.read_tag(index, TagType::Byte8)?;
+        self.read_f64()
+    }
+
+    pub fn has_subblock(&mut self, index: u64) -> bool {
+        self.check_tag(index, TagType::Length4)
+    }
+
+    /// Run `f` over the contents of a tagged sub-block, then skip any bytes it didn't read.
+    pub fn read_subblock<T>(
+        &mut self,
+        index: u64,
+        f: impl FnOnce(&mut Self) -> eyre::Result<T>,
+    ) -> eyre::Result<T> {
+        self.read_tag(index, TagType::Length4)?;
+        let size = self.read_u32()? as usize;
+
+        self.with_limit(size, f)
+    }
+
+    /// Restrict reads to the next `size` bytes while running `f`.
+    fn with_limit<T>(
+        &mut self,
+        size: usize,
+        f: impl FnOnce(&mut Self) -> eyre::Result<T>,
+    ) -> eyre::Result<T> {
+        if size > self.remaining() {
+            return Err(eyre::eyre!(
+                "block of {size} bytes at offset {} overruns its parent",
+                self.pos
+            ));
+        }
+
+        let end = self.pos + size;
+        self.ends.push(end);
+        let result = f(self);
+        self.ends.pop();
+        self.pos = end;
+
+        result
+    }
+
+    /// Read a top-level block header and run `f` over its contents.
+    ///
+    /// The reader always ends up at the start of the next block, even if `f` fails.
+    pub fn read_block<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self, BlockInfo) -> eyre::Result<T>,
+    ) -> eyre::Result<T> {
+        let size = self.read_u32()? as usize;
+        let _unknown = self.read_u8()?;
+        let min_version = self.read_u8()?;
+        let current_version = self.read_u8()?;
+        let block_type = self.read_u8()?;
+
+        let info = BlockInfo {
+            offset: self.pos,
+            size,
+            block_type,
+            min_version,
+            current_version,
+        };
+
+        self.with_limit(size, |r| f(r, info))
+    }
+
+    /// Read a length-prefixed string with its ASCII flag.
+    fn read_string_body(&mut self) -> eyre::Result<String> {
+        let length = self.read_varuint()? as usize;
+        let _is_ascii = self.read_u8()?;
+        let bytes = self.read_bytes(length)?;
+
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    pub fn read_string(&mut self, index: u64) -> eyre::Result<String> {
+        self.read_subblock(index, |r| r.read_string_body())
+    }
+
+    /// Read a string that may be followed by a formatting code.
+    pub fn read_string_with_format(&mut self, index: u64) -> eyre::Result<(String, Option<u32>)> {
+        self.read_subblock(index, |r| {
+            let string = r.read_string_body()?;
+            let format = match r.check_tag(2, TagType::Byte4) {
+                true => Some(r.read_int(2)?),
+                false => None,
+            };
+
+            Ok((string, format))
+        })
+    }
+
+    /// Read a pair of `u32`s stored in a sub-block.
+    pub fn read_int_pair(&mut self, index: u64) -> eyre::Result<(u32, u32)> {
+        self.read_subblock(index, |r| Ok((r.read_u32()?, r.read_u32()?)))
+    }
+
+    fn read_lww<T>(
+        &mut self,
+        index: u64,
+        f: impl FnOnce(&mut Self) -> eyre::Result<T>,
+    ) -> eyre::Result<LwwValue<T>> {
+        self.read_subblock(index, |r| {
+            Ok(LwwValue {
+                timestamp: r.read_id(1)?,
+                value: f(r)?,
+            })
+        })
+    }
+
+    pub fn read_lww_bool(&mut self, index: u64) -> eyre::Result<LwwValue<bool>> {
+        self.read_lww(index, |r| r.read_bool(2))
+    }
+
+    pub fn read_lww_byte(&mut self, index: u64) -> eyre::Result<LwwValue<u8>> {
+        self.read_lww(index, |r| r.read_byte(2))
+    }
+
+    pub fn read_lww_float(&mut self, index: u64) -> eyre::Result<LwwValue<f32>> {
+        self.read_lww(index, |r| r.read_float(2))
+    }
+
+    pub fn read_lww_id(&mut self, index: u64) -> eyre::Result<LwwValue<CrdtId>> {
+        self.read_lww(index, |r| r.read_id(2))
+    }
+
+    pub fn read_lww_string(&mut self, index: u64) -> eyre::Result<LwwValue<String>> {
+        self.read_lww(index, |r| r.read_string(2))
+    }
+}
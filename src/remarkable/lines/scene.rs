@@ -0,0 +1,315 @@
+//! The in-memory page model shared by every `.rm` version.
+
+use std::collections::HashMap;
+
+use super::{
+    crdt, Block, CrdtId, GlyphRange, Line, LwwValue, RootText, SceneItem, TextItem, TreeNode,
+};
+
+/// The node every layer of a v6 scene tree hangs off of.
+pub const ROOT_NODE: CrdtId = CrdtId::new(0, 1);
+
+/// Special anchors for groups placed relative to the start and end of the page's text.
+const TEXT_START: CrdtId = CrdtId::new(0, 0xFFFF_FFFF_FFFE);
+const TEXT_END: CrdtId = CrdtId::new(0, 0xFFFF_FFFF_FFFF);
+
+/// Height of a line of typed text.
+const TEXT_LINE_HEIGHT: f32 = 71.0;
+
+/// The drawable contents of a single page.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Page {
+    /// The `.rm` version the page was read from.
+    pub version: u8,
+    /// Layers from bottom to top.
+    pub layers: Vec<Layer>,
+    /// Typed text, only present in v6 pages.
+    pub text: Option<RootText>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub id: CrdtId,
+    pub name: String,
+    pub visible: bool,
+    /// Strokes in the order they're drawn.
+    pub lines: Vec<Line>,
+    /// Highlighted text in PDF and EPUB documents.
+    pub glyphs: Vec<GlyphRange>,
+}
+
+impl Layer {
+    fn new(id: CrdtId, node: Option<&TreeNode>) -> Self {
+        Self {
+            id,
+            name: node.map(|n| n.label.value.clone()).unwrap_or_default(),
+            visible: node.is_none_or(|n| n.visible.value),
+            lines: Vec::new(),
+            glyphs: Vec::new(),
+        }
+    }
+}
+
+/// An item in the children of a group.
+#[derive(Debug, Clone)]
+enum Child {
+    Group(CrdtId),
+    Line(Line),
+    Glyph(GlyphRange),
+}
+
+impl Page {
+    /// Build a page from the blocks of a v6 file, resolving the CRDT scene tree into the state
+    /// displayed on the device.
+    pub fn from_blocks(blocks: Vec<Block>) -> Self {
+        // where each group currently lives, the last declaration winning
+        let mut parents: HashMap<CrdtId, CrdtId> = HashMap::new();
+        let mut nodes: HashMap<CrdtId, TreeNode> = HashMap::new();
+        let mut children: HashMap<CrdtId, Vec<SceneItem<Child>>> = HashMap::new();
+        let mut text: Option<RootText> = None;
+
+        for block in blocks {
+            match block {
+                Block::SceneTree(tree) => {
+                    parents.insert(tree.tree_id, tree.parent_id);
+                }
+                Block::TreeNode(node) => {
+                    let node = match nodes.remove(&node.node_id) {
+                        Some(old) => merge_nodes(old, node),
+                        None => node,
+                    };
+                    nodes.insert(node.node_id, node);
+                }
+                Block::SceneGroupItem(item) => {
+                    push_child(&mut children, item, |id| Some(Child::Group(id)))
+                }
+                Block::SceneLineItem(item) => {
+                    push_child(&mut children, item, |line| Some(Child::Line(line)))
+                }
+                Block::SceneGlyphItem(item) => {
+                    push_child(&mut children, item, |glyph| Some(Child::Glyph(glyph)))
+                }
+                Block::SceneTombstoneItem(item) => push_child(&mut children, item, |()| None),
+                Block::RootText(root) => text = Some(root),
+                _ => (),
+            }
+        }
+
+        let mut children: HashMap<CrdtId, Vec<Child>> = children
+            .into_iter()
+            .map(|(parent, items)| {
+                let ordered = crdt::resolve(items)
+                    .into_iter()
+                    .filter_map(|item| match item.value {
+                        // groups that have since been moved elsewhere are stale
+                        Some(Child::Group(id))
+                            if parents.get(&id).is_some_and(|p| *p != item.parent_id) =>
+                        {
+                            None
+                        }
+                        value => value,
+                    })
+                    .collect();
+
+                (parent, ordered)
+            })
+            .collect();
+
+        let text = text.map(RootText::resolve);
+        let anchors = text.as_ref().map(RootText::anchors).unwrap_or_default();
+
+        let mut tree = Tree {
+            children: &mut children,
+            nodes: &nodes,
+            anchors: &anchors,
+        };
+
+        let layers = tree
+            .children
+            .remove(&ROOT_NODE)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|child| match child {
+                Child::Group(id) => {
+                    let mut layer = Layer::new(id, nodes.get(&id));
+                    tree.collect(id, (0.0, 0.0), &mut layer);
+                    Some(layer)
+                }
+                _ => {
+                    tracing::warn!("ignoring an item outside of any layer");
+                    None
+                }
+            })
+            .collect();
+
+        Self {
+            version: 6,
+            layers,
+            text,
+        }
+    }
+
+    /// Iterate over the strokes of every visible layer, bottom to top.
+    pub fn visible_lines(&self) -> impl Iterator<Item = &Line> {
+        self.layers
+            .iter()
+            .filter(|l| l.visible)
+            .flat_map(|l| l.lines.iter())
+    }
+}
+
+fn push_child<T>(
+    children: &mut HashMap<CrdtId, Vec<SceneItem<Child>>>,
+    item: SceneItem<T>,
+    f: impl FnOnce(T) -> Option<Child>,
+) {
+    children.entry(item.parent_id).or_default().push(SceneItem {
+        parent_id: item.parent_id,
+        item_id: item.item_id,
+        left_id: item.left_id,
+        right_id: item.right_id,
+        deleted_length: item.deleted_length,
+        value: item.value.and_then(f),
+    });
+}
+
+/// The resolved scene tree, consumed as it's flattened into layers.
+struct Tree<'a> {
+    children: &'a mut HashMap<CrdtId, Vec<Child>>,
+    nodes: &'a HashMap<CrdtId, TreeNode>,
+    anchors: &'a HashMap<CrdtId, f32>,
+}
+
+impl Tree<'_> {
+    /// Flatten the contents of `group` (and any groups nested in it) into `layer`, in order.
+    ///
+    /// Groups anchored to the text are moved along with it, so their strokes are offset into
+    /// page coordinates here. Children are taken out of the map as they're visited, so cycles
+    /// terminate.
+    fn collect(&mut self, group: CrdtId, offset: (f32, f32), layer: &mut Layer) {
+        let (mut dx, mut dy) = offset;
+        if let Some(anchor) = self.nodes.get(&group).and_then(|n| n.anchor.as_ref()) {
+            dx += anchor.origin_x.value;
+            dy += self
+                .anchors
+                .get(&anchor.id.value)
+                .copied()
+                .unwrap_or_default();
+        }
+
+        for child in self.children.remove(&group).unwrap_or_default() {
+            match child {
+                Child::Group(id) => self.collect(id, (dx, dy), layer),
+                Child::Line(mut line) => {
+                    for point in &mut line.points {
+                        point.x += dx;
+                        point.y += dy;
+                    }
+                    layer.lines.push(line);
+                }
+                Child::Glyph(glyph) => layer.glyphs.push(glyph),
+            }
+        }
+    }
+}
+
+fn merge_nodes(old: TreeNode, new: TreeNode) -> TreeNode {
+    TreeNode {
+        node_id: new.node_id,
+        label: old.label.merge(new.label),
+        visible: old.visible.merge(new.visible),
+        anchor: new.anchor.or(old.anchor),
+    }
+}
+
+impl RootText {
+    /// Order the text's characters and drop deleted ones.
+    fn resolve(self) -> Self {
+        let mut styles: HashMap<CrdtId, LwwValue<_>> = HashMap::new();
+        for (id, style) in self.styles {
+            let style = match styles.remove(&id) {
+                Some(old) => old.merge(style),
+                None => style,
+            };
+            styles.insert(id, style);
+        }
+
+        let mut styles: Vec<_> = styles.into_iter().collect();
+        styles.sort_by_key(|(id, _)| *id);
+
+        let items = self.items.into_iter().flat_map(split_chars);
+
+        Self {
+            items: crdt::resolve(items),
+            styles,
+            ..self
+        }
+    }
+
+    /// The visible text, in order.
+    pub fn plain_text(&self) -> String {
+        self.items.iter().map(|i| i.text.as_str()).collect()
+    }
+
+    /// The vertical position of every character, for placing groups anchored to them.
+    ///
+    /// Each paragraph takes up one line of the template's grid.
+    fn anchors(&self) -> HashMap<CrdtId, f32> {
+        let mut y = self.pos_y as f32;
+        let mut anchors = HashMap::from([(TEXT_START, y)]);
+
+        for item in &self.items {
+            anchors.insert(item.item_id, y);
+
+            if item.text == "\n" {
+                y += TEXT_LINE_HEIGHT;
+            }
+        }
+
+        if !self.plain_text().ends_with('\n') {
+            y += TEXT_LINE_HEIGHT;
+        }
+        anchors.insert(TEXT_END, y);
+
+        anchors
+    }
+}
+
+/// Split a text item into one item per character.
+///
+/// Each character of an item has its own ID (counting up from the item's), and other items
+/// may be inserted next to any of them.
+fn split_chars(item: TextItem) -> Vec<TextItem> {
+    let chars: Vec<char> = item.text.chars().collect();
+    let length = match chars.len() {
+        0 => item.deleted_length as usize,
+        n => n,
+    };
+
+    // formatting markers and single characters are already atomic
+    if length <= 1 {
+        return vec![item];
+    }
+
+    let id = |i: usize| CrdtId::new(item.item_id.part1, item.item_id.part2 + i as u64);
+
+    (0..length)
+        .map(|i| TextItem {
+            item_id: id(i),
+            left_id: match i {
+                0 => item.left_id,
+                _ => id(i - 1),
+            },
+            right_id: match i + 1 == length {
+                true => item.right_id,
+                false => id(i + 1),
+            },
+            deleted_length: match chars.is_empty() {
+                true => 1,
+                false => 0,
+            },
+            text: chars.get(i).map(char::to_string).unwrap_or_default(),
+            format: None,
+        })
+        .collect()
+}
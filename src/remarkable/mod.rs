@@ -1,31 +1,70 @@
 use std::{
+    collections::{HashMap, HashSet},
     path::{Component, Path, PathBuf},
-    sync::Arc,
-    time::Duration,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
 };
 
 use color_eyre::eyre;
-use dashmap::{DashMap, DashSet};
-use futures::{stream, StreamExt};
+use dashmap::{mapref::entry::Entry, DashMap, DashSet};
+use futures::{future, stream, StreamExt};
 use notify::{Event, RecursiveMode, Watcher};
 use uuid::Uuid;
 
 pub mod disk;
+pub mod lines;
+pub mod names;
+pub mod order;
+#[cfg(test)]
+pub mod testing;
 
 /// Time between file re-polls. Files are only read when updated, but batch updated when changed every POLL_DURATION
 const POLL_DURATION: Duration = Duration::from_secs(2);
 
+/// The extension of the file keeping the [`Origin`] of a trashed element, next to its files.
+///
+/// It isn't one of the tablet's own, so elements the tablet trashed have none.
+const ORIGIN_EXTENSION: &str = "origin";
+
+/// The extension of files uploads are written to before they're imported, which are hidden
+/// next to the documents so they're moved into place rather than copied.
+const UPLOAD_EXTENSION: &str = "upload";
+
 pub const PINNED_DIRECTORY: &str = "Favorites";
 pub const TRASH_DIRECTORY: &str = "Trash";
+/// Where elements that can't be reached from the root or the trash are shown.
+pub const LOST_DIRECTORY: &str = "Lost+Found";
+
+/// The names at the root taken by folders that only list elements kept elsewhere, which
+/// elements at the root are told apart from like from a sibling sharing their name.
+const VIRTUAL_DIRECTORIES: [&str; 3] = [PINNED_DIRECTORY, TRASH_DIRECTORY, LOST_DIRECTORY];
 
 /// A thread-safe representation of the reMarkable filesystem
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Default)]
 pub struct Remarkable {
     /// The base path to the document filesystem.
     /// On the reMarkable device, this is '/home/root/.local/share/remarkable/xochitl/'.
     base: PathBuf,
 
     elements: DashMap<Uuid, Arc<Element>>,
+
+    /// The elements in each folder, deleted ones included, kept in step with `elements`.
+    children: DashMap<Parent, HashSet<Uuid>>,
+
+    /// Elements already found by their path, emptied whenever one is added, removed, renamed
+    /// or moved.
+    paths: DashMap<PathBuf, Uuid>,
+
+    /// Elements in folders that don't exist, or in a cycle of folders, with everything in them
+    /// shown in [`LOST_DIRECTORY`] instead.
+    lost: DashSet<Uuid>,
+
+    /// Counts the times `paths` was emptied, so lookups racing a change don't fill it back in
+    /// with what they found before it.
+    generation: AtomicU64,
 }
 
 impl Remarkable {
@@ -43,64 +82,376 @@ impl Remarkable {
         me
     }
 
+    /// Find the element at `path` by walking down from the root one folder at a time.
     fn uuid_from_path(&self, path: impl AsRef<Path>) -> Option<(Uuid, Arc<Element>)> {
-        let mut components = path.as_ref().components();
+        let path = path.as_ref();
+
+        // copied out first, as changes lock `elements` before emptying `paths`
+        let cached = self.paths.get(path).map(|uuid| *uuid);
+        if let Some(uuid) = cached {
+            if let Some(element) = self.elements.get(&uuid).map(|e| e.value().clone()) {
+                return Some((uuid, element));
+            }
+        }
+
+        let generation = self.generation.load(Ordering::SeqCst);
+        let found = self.walk(path)?;
 
-        let name = match components.next_back()? {
-            Component::Normal(os) => os.to_string_lossy(),
-            _ => return None,
+        self.paths.insert(path.to_path_buf(), found.0);
+        // anything changed since the walk started may have emptied the cache before this
+        if self.generation.load(Ordering::SeqCst) != generation {
+            self.paths.remove_if(path, |_, uuid| *uuid == found.0);
+        }
+
+        Some(found)
+    }
+
+    /// Find the element at `path`, one segment at a time, without the cache.
+    fn walk(&self, path: &Path) -> Option<(Uuid, Arc<Element>)> {
+        let mut segments = path
+            .components()
+            .filter(|c| !matches!(c, Component::RootDir | Component::CurDir))
+            .peekable();
+
+        // "/Favorites", "/Trash" and "/Lost+Found" stand for those, folders of that name being
+        // shown apart from them
+        let mut children = match segments.peek() {
+            Some(Component::Normal(name)) if *name == PINNED_DIRECTORY => {
+                segments.next();
+                Self::name_apart(self.pinned())
+            }
+            Some(Component::Normal(name)) if *name == TRASH_DIRECTORY => {
+                segments.next();
+                self.named_children(Parent::Trash)
+            }
+            Some(Component::Normal(name)) if *name == LOST_DIRECTORY => {
+                segments.next();
+                self.named_lost()
+            }
+            _ => self.named_children(Parent::Root),
         };
 
-        let mut candidates: Vec<(Uuid, Arc<Element>)> = self
-            .elements
+        let mut found = None;
+        while let Some(segment) = segments.next() {
+            let Component::Normal(name) = segment else {
+                return None;
+            };
+            let name = name.to_string_lossy();
+
+            let (uuid, element, _) = children.into_iter().find(|(_, _, shown)| *shown == name)?;
+
+            if segments.peek().is_some() && !element.is_dir() {
+                return None;
+            }
+
+            children = self.named_children(Parent::Directory(uuid));
+            found = Some((uuid, element));
+        }
+
+        found
+    }
+
+    /// The path the element `uuid` is shown at through its folders, rather than through the
+    /// favorites, or `None` if it isn't shown anywhere.
+    pub fn path_of(&self, uuid: &Uuid) -> Option<PathBuf> {
+        let mut segments = Vec::new();
+        let mut seen = HashSet::new();
+        let mut uuid = *uuid;
+
+        loop {
+            if !seen.insert(uuid) {
+                return None;
+            }
+
+            let parent = self.elements.get(&uuid)?.parent;
+            let lost = self.lost.contains(&uuid);
+            let siblings = match lost {
+                true => self.named_lost(),
+                false => self.named_children(parent),
+            };
+            let (_, _, name) = siblings.into_iter().find(|(u, _, _)| *u == uuid)?;
+            segments.push(name);
+
+            match parent {
+                _ if lost => segments.push(LOST_DIRECTORY.into()),
+                Parent::Root => {}
+                Parent::Trash => segments.push(TRASH_DIRECTORY.into()),
+                Parent::Directory(folder) => {
+                    uuid = folder;
+                    continue;
+                }
+            }
+
+            return Some(segments.into_iter().rev().collect());
+        }
+    }
+
+    /// The elements directly in `parent`, deleted ones included.
+    fn children(&self, parent: Parent) -> Vec<(Uuid, Arc<Element>)> {
+        // copied out first, so `elements` is never locked while `children` is
+        let uuids: Vec<Uuid> = match self.children.get(&parent) {
+            Some(children) => children.iter().copied().collect(),
+            None => return Vec::new(),
+        };
+
+        uuids
+            .into_iter()
+            .filter_map(|uuid| Some((uuid, self.elements.get(&uuid)?.value().clone())))
+            .collect()
+    }
+
+    /// The elements directly in `parent` that aren't deleted, with the names they're shown with
+    /// in paths as given by [`names::encode`], oldest first.
+    ///
+    /// Siblings can share a name, in which case the oldest keeps it and the others are told
+    /// apart as `Notes (2)`, `Notes (3)` and so on, skipping names a sibling already has.
+    /// Documents count with the extension they're served with too, so a folder named `X.pdf`
+    /// and a document `X` are told apart the same way. At the root, the [`VIRTUAL_DIRECTORIES`] keep theirs, so a folder named `Trash` there is
+    /// shown as `Trash (2)`.
+    fn named_children(&self, parent: Parent) -> Vec<(Uuid, Arc<Element>, String)> {
+        let mut children = self.children(parent);
+        // those are shown in Lost+Found, so cycles of folders aren't shown in themselves
+        children.retain(|(uuid, _)| !self.lost.contains(uuid));
+
+        match parent {
+            Parent::Root => Self::name_apart_from(children, &VIRTUAL_DIRECTORIES),
+            _ => Self::name_apart(children),
+        }
+    }
+
+    /// The elements shown in [`LOST_DIRECTORY`], with the names they're shown with there.
+    fn named_lost(&self) -> Vec<(Uuid, Arc<Element>, String)> {
+        // copied out first, so `elements` is never locked while `lost` is
+        let lost: Vec<Uuid> = self.lost.iter().map(|uuid| *uuid).collect();
+        let lost = lost
+            .into_iter()
+            .filter_map(|uuid| Some((uuid, self.elements.get(&uuid)?.value().clone())))
+            .collect();
+        Self::name_apart(lost)
+    }
+
+    fn name_apart(children: Vec<(Uuid, Arc<Element>)>) -> Vec<(Uuid, Arc<Element>, String)> {
+        Self::name_apart_from(children, &[])
+    }
+
+    /// Like [`Self::name_apart`], with the names in `reserved` taken before any sibling's.
+    fn name_apart_from(
+        mut children: Vec<(Uuid, Arc<Element>)>,
+        reserved: &[&str],
+    ) -> Vec<(Uuid, Arc<Element>, String)> {
+        children.retain(|(_, e)| !e.is_deleted());
+        children.sort_by_key(|(uuid, e)| (e.created, *uuid));
+
+        let names: Vec<String> = children
             .iter()
-            .filter(|e| e.name == name)
-            .map(|r| (r.key().clone(), r.value().clone()))
+            .map(|(_, e)| names::encode(&e.name))
             .collect();
+        // documents are also served with an extension, which can make them look like a sibling
+        let forms = |name: &str, element: &Element| match element.format() {
+            Some(format) => vec![
+                name.to_string(),
+                format!("{name}.{}", format.served_extension()),
+            ],
+            None => vec![name.to_string()],
+        };
 
-        // if there are no elements with the same name, it must not exist
-        if candidates.is_empty() {
-            return None;
+        let mut taken: HashSet<String> = children
+            .iter()
+            .zip(&names)
+            .flat_map(|((_, element), name)| forms(name, element))
+            .collect();
+        let mut shown: HashSet<String> = reserved.iter().map(|name| name.to_string()).collect();
+        taken.extend(shown.iter().cloned());
+
+        children
+            .into_iter()
+            .zip(names)
+            .map(|((uuid, element), mut name)| {
+                if forms(&name, &element)
+                    .iter()
+                    .any(|form| shown.contains(form))
+                {
+                    let mut n = 2;
+                    while forms(&format!("{name} ({n})"), &element)
+                        .iter()
+                        .any(|form| taken.contains(form))
+                    {
+                        n += 1;
+                    }
+                    name = format!("{name} ({n})");
+                }
+
+                for form in forms(&name, &element) {
+                    taken.insert(form.clone());
+                    shown.insert(form);
+                }
+
+                (uuid, element, name)
+            })
+            .collect()
+    }
+
+    /// Add or replace the element `uuid`, moving it between folders in the index.
+    fn insert_element(&self, uuid: Uuid, element: Element) {
+        let element = Arc::new(element);
+
+        // the entry stays locked until the index is updated, so updates to the same element
+        // can't interleave
+        let old = match self.elements.entry(uuid) {
+            Entry::Occupied(mut entry) => {
+                let old = entry.insert(element.clone());
+                if old.parent != element.parent {
+                    self.unlink(old.parent, &uuid);
+                    self.link(element.parent, uuid);
+                }
+                Some(old)
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(element.clone());
+                self.link(element.parent, uuid);
+                None
+            }
+        };
+
+        // pinning moves it in or out of the favorites
+        let moved = old.as_ref().is_none_or(|old| {
+            (&old.name, old.parent, old.pinned, old.deleted, old.created)
+                != (
+                    &element.name,
+                    element.parent,
+                    element.pinned,
+                    element.deleted,
+                    element.created,
+                )
+        });
+        if moved {
+            self.forget_paths();
         }
 
-        // if the name is unique, return that
-        if candidates.len() == 1 {
-            return candidates.pop();
+        // moving a folder can make whatever's in it lost, or found again
+        if old.is_some_and(|old| old.parent != element.parent) {
+            self.find_lost();
         }
+    }
 
-        candidates
-            .iter()
-            .find(|(_, elem)| self.path_matches(&path, &elem))
-            .cloned()
+    /// Remove the element `uuid` from the filesystem and the index, but not from disk.
+    fn remove_element(&self, uuid: &Uuid) {
+        if let Entry::Occupied(entry) = self.elements.entry(*uuid) {
+            self.unlink(entry.get().parent, uuid);
+            entry.remove();
+            self.lost.remove(uuid);
+            self.forget_paths();
+        }
+    }
+
+    fn link(&self, parent: Parent, uuid: Uuid) {
+        self.children.entry(parent).or_default().insert(uuid);
+    }
+
+    fn unlink(&self, parent: Parent, uuid: &Uuid) {
+        if let Entry::Occupied(mut children) = self.children.entry(parent) {
+            children.get_mut().remove(uuid);
+            if children.get().is_empty() {
+                children.remove();
+            }
+        }
     }
 
-    /// A method for verifying that an element exists at a given path
-    fn path_matches(&self, path: impl AsRef<Path>, elem: &Element) -> bool {
-        match (path.as_ref().parent(), elem.parent) {
-            // elem parent's UUID exists in self.elements & is a directory that ends with the current path, AND is itself valid
-            (Some(parent), Parent::Directory(uuid)) => self
-                .elements
-                .get(&uuid)
-                .map(|v| v.value().clone())
-                .filter(|v| v.is_dir() && parent.ends_with(&v.name))
-                .is_some_and(|v| self.path_matches(parent, &v)),
+    /// Find the elements that can't be reached from the root or the trash, as their folder
+    /// doesn't exist or is in a cycle of folders, to show them in [`LOST_DIRECTORY`].
+    fn find_lost(&self) {
+        let elements: HashMap<Uuid, Arc<Element>> = self
+            .elements
+            .iter()
+            .map(|e| (*e.key(), e.value().clone()))
+            .collect();
+
+        let mut reachable: HashMap<Uuid, bool> = HashMap::new();
+        let mut lost = HashSet::new();
+
+        for (&start, element) in &elements {
+            // the elements from `start` up to the first one already known about
+            let mut chain = Vec::new();
+            let (mut uuid, mut element) = (start, element);
+
+            let found = loop {
+                if let Some(found) = reachable.get(&uuid) {
+                    break *found;
+                }
+
+                if let Some(i) = chain.iter().position(|u| *u == uuid) {
+                    // the folders in a cycle are shown from the oldest one
+                    let cycle = &chain[i..];
+                    let first = cycle
+                        .iter()
+                        .filter(|u| !elements[*u].is_deleted())
+                        .min_by_key(|u| (elements[*u].created, **u));
+                    if let Some(first) = first {
+                        if !self.lost.contains(first) {
+                            tracing::warn!(
+                                uuid = %first,
+                                name = elements[first].name,
+                                ?cycle,
+                                "folders form a cycle, showing it in {LOST_DIRECTORY}"
+                            );
+                        }
+                        lost.insert(*first);
+                    }
+                    break false;
+                }
+                chain.push(uuid);
 
-            // Parent::Trash <=> "/Trash"
-            (Some(t), Parent::Trash) if t == Path::new(TRASH_DIRECTORY) => true,
+                match element.parent {
+                    Parent::Root | Parent::Trash => break true,
+                    Parent::Directory(parent) => match elements.get(&parent) {
+                        Some(folder) if folder.is_dir() => (uuid, element) = (parent, folder),
+                        folder => {
+                            if !element.is_deleted() {
+                                if !self.lost.contains(&uuid) {
+                                    tracing::warn!(
+                                        %uuid,
+                                        name = element.name,
+                                        %parent,
+                                        parent_exists = folder.is_some(),
+                                        "element isn't in a folder, showing it in {LOST_DIRECTORY}"
+                                    );
+                                }
+                                lost.insert(uuid);
+                            }
+                            break false;
+                        }
+                    },
+                }
+            };
 
-            // Parent::Root <=> "/" (no parent)
-            (None, Parent::Root) => true,
+            reachable.extend(chain.into_iter().map(|uuid| (uuid, found)));
+        }
 
-            // if all else, false
-            _ => true,
+        let changed = self.lost.len() != lost.len() || lost.iter().any(|u| !self.lost.contains(u));
+        if changed {
+            for uuid in &lost {
+                self.lost.insert(*uuid);
+            }
+            self.lost.retain(|uuid| lost.contains(uuid));
+            self.forget_paths();
         }
     }
 
+    /// Whether any elements are shown in [`LOST_DIRECTORY`].
+    pub fn has_lost(&self) -> bool {
+        !self.lost.is_empty()
+    }
+
+    fn forget_paths(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.paths.clear();
+    }
+
     /// Updates an element in the Filesystem by it's Uuid
     async fn update_element(&self, uuid: Uuid) -> eyre::Result<()> {
         match disk::read(&self.base, &uuid).await {
             Ok(element) => {
-                self.elements.insert(uuid, Arc::new(element));
+                self.insert_element(uuid, element);
                 Ok(())
             }
             Err(err) => Err(eyre::eyre!("failed to read {uuid} from disk: {err}")),
@@ -120,6 +471,9 @@ impl Remarkable {
         };
 
         self.elements.clear();
+        self.children.clear();
+        self.lost.clear();
+        self.forget_paths();
 
         stream::iter(dir)
             // filter by Ok entries' paths
@@ -142,6 +496,8 @@ impl Remarkable {
             })
             .await;
 
+        self.find_lost();
+
         tracing::debug!("finished indexing");
     }
 
@@ -201,12 +557,14 @@ impl Remarkable {
         loop {
             tokio::time::sleep(POLL_DURATION).await;
 
+            let changed = !to_delete.is_empty() || !to_update.is_empty();
+
             // remove all elements who were deleted
             to_delete.iter().for_each(|uuid| {
                 let uuid = uuid.key();
 
                 tracing::debug!("removing {uuid}");
-                self.elements.remove(uuid);
+                self.remove_element(uuid);
             });
             to_delete.clear();
 
@@ -224,10 +582,20 @@ impl Remarkable {
                 .await;
 
             to_update.clear();
+
+            // folders may have gone missing, or turned up again
+            if changed {
+                self.find_lost();
+            }
         }
     }
 
-    pub async fn list(&self, path: impl AsRef<Path>) -> Result<Vec<Arc<Element>>, eyre::Error> {
+    /// List the elements in the directory at `path`, with their UUIDs and the names they're
+    /// shown with in paths.
+    pub async fn list(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<Vec<(Uuid, Arc<Element>, String)>, eyre::Error> {
         // strip prefixed slash for flexibility with Trash and Pinned
         let path = match path.as_ref().strip_prefix("/") {
             Ok(stripped) => stripped,
@@ -239,96 +607,581 @@ impl Remarkable {
             return Err(eyre::eyre!("list called on a file"));
         }
 
-        // if path is root, simply return all files pointing to the root
-        if path.parent().is_none() {
-            return Ok(self
-                .elements
-                .iter()
-                .filter(|e| e.value().parent == Parent::Root)
-                .map(|v| v.value().clone())
-                .collect());
+        if path == Path::new(PINNED_DIRECTORY) {
+            return Ok(Self::name_apart(self.pinned()));
+        }
+        if path == Path::new(LOST_DIRECTORY) {
+            return Ok(self.named_lost());
         }
 
-        let Some((uuid, _)) = self.uuid_from_path(&path) else {
+        let Some(parent) = self.parent_at(path) else {
             return Err(eyre::eyre!("no uuid found for directory {path:?}"));
         };
 
-        let children = self
+        Ok(self.named_children(parent))
+    }
+
+    /// The folder at `path` as the parent of elements in it, `""` or `"/"` being the root.
+    pub fn parent_at(&self, path: impl AsRef<Path>) -> Option<Parent> {
+        let path = path.as_ref().strip_prefix("/").unwrap_or(path.as_ref());
+
+        if path.as_os_str().is_empty() {
+            return Some(Parent::Root);
+        }
+        if path == Path::new(TRASH_DIRECTORY) {
+            return Some(Parent::Trash);
+        }
+
+        match self.uuid_from_path(path) {
+            Some((uuid, element)) if element.is_dir() => Some(Parent::Directory(uuid)),
+            _ => None,
+        }
+    }
+
+    /// Find the element at `path`.
+    pub fn element(&self, path: impl AsRef<Path>) -> Option<(Uuid, Arc<Element>)> {
+        let path = path.as_ref().strip_prefix("/").unwrap_or(path.as_ref());
+
+        self.uuid_from_path(path)
+    }
+
+    /// Read the page at `index` (starting from zero) of the document at `path`.
+    pub async fn read_page(
+        &self,
+        path: impl AsRef<Path>,
+        index: usize,
+    ) -> eyre::Result<lines::Page> {
+        let path = path.as_ref();
+
+        let Some((uuid, element)) = self.element(path) else {
+            return Err(eyre::eyre!("{path:?} not found"));
+        };
+
+        let Some(page) = element.pages()?.get(index) else {
+            return Err(eyre::eyre!("{path:?} has no page {index}"));
+        };
+
+        lines::read_page(&self.base, &uuid, &page.id).await
+    }
+
+    /// Read every page of the document `uuid`, in order.
+    pub async fn read_pages(&self, uuid: &Uuid) -> eyre::Result<Vec<lines::Page>> {
+        let Some(element) = self.elements.get(uuid).map(|e| e.value().clone()) else {
+            return Err(eyre::eyre!("{uuid} not found"));
+        };
+
+        future::try_join_all(
+            element
+                .pages()?
+                .iter()
+                .map(|page| lines::read_page(&self.base, uuid, &page.id)),
+        )
+        .await
+    }
+
+    /// Read the original file a PDF or EPUB document was imported from.
+    pub async fn read_original(&self, uuid: &Uuid) -> eyre::Result<Vec<u8>> {
+        match self.elements.get(uuid).and_then(|e| e.format()) {
+            Some(Format::Pdf) => self.read_file(uuid, "pdf").await,
+            Some(Format::Epub) => self.read_file(uuid, "epub").await,
+            _ => Err(eyre::eyre!("{uuid} has no original file")),
+        }
+    }
+
+    /// Read the PDF a PDF or EPUB document is displayed from, which for EPUBs is the
+    /// tablet's own conversion of the original.
+    pub async fn read_pdf(&self, uuid: &Uuid) -> eyre::Result<Vec<u8>> {
+        match self.elements.get(uuid).and_then(|e| e.format()) {
+            Some(Format::Pdf | Format::Epub) => self.read_file(uuid, "pdf").await,
+            _ => Err(eyre::eyre!("{uuid} has no PDF")),
+        }
+    }
+
+    /// Read the file stored next to the document `uuid` with `extension`.
+    async fn read_file(&self, uuid: &Uuid, extension: &str) -> eyre::Result<Vec<u8>> {
+        let mut path = self.base.join(uuid.to_string());
+        path.set_extension(extension);
+
+        Ok(tokio::fs::read(path).await?)
+    }
+
+    /// Read a file the server keeps next to the files of the element `uuid`, with
+    /// `extension`, or `None` if there isn't one.
+    ///
+    /// These are moved, copied and deleted along with the element, like the tablet's own files.
+    pub async fn read_sidecar(
+        &self,
+        uuid: &Uuid,
+        extension: &str,
+    ) -> eyre::Result<Option<Vec<u8>>> {
+        match self.read_file(uuid, extension).await {
+            Ok(contents) => Ok(Some(contents)),
+            Err(err) => match err.downcast_ref::<std::io::Error>() {
+                Some(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                _ => Err(err),
+            },
+        }
+    }
+
+    /// Write a file next to the files of the element `uuid`, to be read by [`Self::read_sidecar`].
+    pub async fn write_sidecar(
+        &self,
+        uuid: &Uuid,
+        extension: &str,
+        contents: &[u8],
+    ) -> eyre::Result<()> {
+        let mut path = self.base.join(uuid.to_string());
+        path.set_extension(extension);
+
+        Ok(tokio::fs::write(path, contents).await?)
+    }
+
+    /// Remove a file written by [`Self::write_sidecar`], if there is one.
+    async fn remove_sidecar(&self, uuid: &Uuid, extension: &str) -> eyre::Result<()> {
+        let mut path = self.base.join(uuid.to_string());
+        path.set_extension(extension);
+
+        match tokio::fs::remove_file(path).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Import a PDF or EPUB `file` as a new document named `name` in `parent`, as tests do
+    /// without writing it out first.
+    #[cfg(test)]
+    pub async fn import(
+        &self,
+        parent: Parent,
+        name: &str,
+        format: Format,
+        file: &[u8],
+        page_count: Option<u32>,
+    ) -> eyre::Result<Uuid> {
+        let upload = self.new_upload();
+        tokio::fs::write(upload.path(), file).await?;
+
+        self.import_upload(parent, name, format, upload, page_count)
+            .await
+    }
+
+    /// A file next to the documents for an upload to be written to, before it's imported
+    /// with [`Self::import_upload`]. The tablet doesn't read it.
+    pub fn new_upload(&self) -> Upload {
+        Upload(
+            self.base
+                .join(format!(".{}.{UPLOAD_EXTENSION}", Uuid::new_v4())),
+        )
+    }
+
+    /// Import the PDF or EPUB written to `upload` as a new document named `name` in `parent`,
+    /// moving it into place rather than copying it.
+    pub async fn import_upload(
+        &self,
+        parent: Parent,
+        name: &str,
+        format: Format,
+        upload: Upload,
+        page_count: Option<u32>,
+    ) -> eyre::Result<Uuid> {
+        let extension = match format {
+            Format::Pdf => "pdf",
+            Format::Epub => "epub",
+            Format::Notebook => return Err(eyre::eyre!("notebooks can't be imported")),
+        };
+
+        let uuid = self.new_uuid();
+        let mut path = self.base.join(uuid.to_string());
+        path.set_extension(extension);
+        let size = tokio::fs::metadata(upload.path()).await?.len();
+        tokio::fs::rename(upload.path(), path).await?;
+
+        let content = disk::Content::imported(format, page_count, size);
+        disk::write(&self.base, &uuid, disk::CONTENT_EXTENSION, &content).await?;
+
+        // the metadata goes last, as it's what makes the document show up
+        let metadata = disk::Metadata::document(name.to_string(), parent);
+        disk::write(&self.base, &uuid, disk::METADATA_EXTENSION, &metadata).await?;
+
+        self.update_element(uuid).await?;
+
+        Ok(uuid)
+    }
+
+    /// Create an empty folder named `name` in `parent`.
+    pub async fn create_directory(&self, parent: Parent, name: &str) -> eyre::Result<Uuid> {
+        let uuid = self.new_uuid();
+
+        let content = disk::DirectoryContent::default();
+        disk::write(&self.base, &uuid, disk::CONTENT_EXTENSION, &content).await?;
+
+        let metadata = disk::Metadata::directory(name.to_string(), parent);
+        disk::write(&self.base, &uuid, disk::METADATA_EXTENSION, &metadata).await?;
+
+        self.update_element(uuid).await?;
+
+        Ok(uuid)
+    }
+
+    /// Whether `uuid` is a folder that's shown somewhere other than the trash.
+    fn is_folder(&self, uuid: &Uuid) -> bool {
+        let shown = self
             .elements
-            .iter()
-            .filter(|e| e.value().parent == Parent::Directory(uuid))
-            .map(|e| e.value().clone())
-            .collect();
+            .get(uuid)
+            .is_some_and(|e| e.is_dir() && !e.is_deleted());
 
-        Ok(children)
+        // only once `elements` isn't locked, as finding ancestors locks it again
+        shown && !self.is_trashed(uuid)
     }
 
-    pub async fn pinned(&self) -> Vec<Arc<Element>> {
-        self.elements
+    /// Whether the element `uuid` is in the trash, directly or in a folder that is.
+    pub fn is_trashed(&self, uuid: &Uuid) -> bool {
+        self.ancestors(uuid).last() == Some(&Parent::Trash)
+    }
+
+    /// Whether the element `uuid` is in the folder `folder`, directly or in a folder that is.
+    pub fn is_within(&self, uuid: &Uuid, folder: &Uuid) -> bool {
+        self.ancestors(uuid).contains(&Parent::Directory(*folder))
+    }
+
+    /// The parent of the element `uuid`, then its parent and so on up to the root or trash.
+    fn ancestors(&self, uuid: &Uuid) -> Vec<Parent> {
+        let mut ancestors = Vec::new();
+        let mut seen = HashSet::from([*uuid]);
+        let mut uuid = *uuid;
+
+        while let Some(parent) = self.elements.get(&uuid).map(|e| e.parent) {
+            ancestors.push(parent);
+
+            match parent {
+                // stops at folders that loop back on themselves
+                Parent::Directory(folder) if seen.insert(folder) => uuid = folder,
+                _ => break,
+            }
+        }
+
+        ancestors
+    }
+
+    /// The elements in the folder `uuid`, then everything in those, and so on.
+    fn descendants(&self, uuid: Uuid) -> Vec<Uuid> {
+        let mut descendants = vec![uuid];
+        let mut next = 0;
+
+        while let Some(parent) = descendants.get(next).copied() {
+            let children: Vec<Uuid> = self
+                .children(Parent::Directory(parent))
+                .into_iter()
+                .map(|(child, _)| child)
+                .filter(|child| !descendants.contains(child))
+                .collect();
+
+            descendants.extend(children);
+            next += 1;
+        }
+
+        descendants.remove(0);
+        descendants
+    }
+
+    /// Move the element `uuid` to the trash, along with everything in it for folders.
+    ///
+    /// The folder each was in is kept, for [`Self::restore`] to put them back in.
+    pub async fn move_to_trash(&self, uuid: Uuid) -> eyre::Result<()> {
+        let trashed_with = uuid;
+
+        for uuid in [uuid].into_iter().chain(self.descendants(uuid)) {
+            if let Some(parent) = self.elements.get(&uuid).map(|e| e.parent) {
+                let origin = serde_json::to_vec(&Origin {
+                    parent,
+                    trashed_with,
+                })?;
+                self.write_sidecar(&uuid, ORIGIN_EXTENSION, &origin).await?;
+            }
+
+            disk::change_parent(&self.base, &uuid, Parent::Trash).await?;
+            self.update_element(uuid).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Take the element `uuid` out of the trash as `name`, back into the folder it was in,
+    /// putting everything that was trashed along with it from inside it back too.
+    ///
+    /// It goes into `parent` instead if the folder it was in is gone, or if it has no
+    /// [`Origin`], as for elements the tablet trashed. Only `uuid` comes out for those.
+    pub async fn restore(&self, uuid: Uuid, parent: Parent, name: &str) -> eyre::Result<()> {
+        let along = self.trashed_along(uuid).await?;
+
+        let origin = self.origin(&uuid).await?.map(|origin| origin.parent);
+        let parent = match origin {
+            Some(Parent::Root) => Parent::Root,
+            Some(Parent::Directory(folder)) if self.is_folder(&folder) => Parent::Directory(folder),
+            _ => parent,
+        };
+
+        self.move_element(uuid, parent, name).await?;
+        self.remove_sidecar(&uuid, ORIGIN_EXTENSION).await?;
+
+        for (trashed, element, origin) in along {
+            self.move_element(trashed, origin, &element.name).await?;
+            self.remove_sidecar(&trashed, ORIGIN_EXTENSION).await?;
+        }
+
+        Ok(())
+    }
+
+    /// The elements in the trash that were trashed along with the element `uuid` from inside
+    /// it, each with the folder it was in, folders before what was in them.
+    ///
+    /// Elements trashed on their own before are left out, even if they were in it.
+    async fn trashed_along(&self, uuid: Uuid) -> eyre::Result<Vec<(Uuid, Arc<Element>, Parent)>> {
+        let Some(Origin { trashed_with, .. }) = self.origin(&uuid).await? else {
+            return Ok(Vec::new());
+        };
+
+        let mut origins = Vec::new();
+        for (trashed, element) in self.trash() {
+            match self.origin(&trashed).await? {
+                Some(origin) if origin.trashed_with == trashed_with => {
+                    origins.push((trashed, element, origin.parent))
+                }
+                _ => {}
+            }
+        }
+
+        let mut along = Vec::new();
+        let mut folders = vec![uuid];
+        while let Some(folder) = folders.pop() {
+            for (trashed, element, origin) in origins
+                .iter()
+                .filter(|(_, _, origin)| *origin == Parent::Directory(folder))
+            {
+                if element.is_dir() {
+                    folders.push(*trashed);
+                }
+                along.push((*trashed, element.clone(), *origin));
+            }
+        }
+
+        Ok(along)
+    }
+
+    /// The [`Origin`] kept for the trashed element `uuid`, if it has one.
+    async fn origin(&self, uuid: &Uuid) -> eyre::Result<Option<Origin>> {
+        match self.read_sidecar(uuid, ORIGIN_EXTENSION).await? {
+            Some(origin) => Ok(Some(serde_json::from_slice(&origin)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Delete the element `uuid` and all of its files for good, along with everything in it
+    /// for folders, and everything trashed along with it from inside it.
+    pub async fn purge(&self, uuid: Uuid) -> eyre::Result<()> {
+        let along = self
+            .trashed_along(uuid)
+            .await?
+            .into_iter()
+            .map(|(u, _, _)| u);
+
+        // children first, so nothing is left without a parent if this fails halfway
+        let descendants = self.descendants(uuid);
+        for uuid in along.chain(descendants).rev().chain([uuid]) {
+            // which takes its origin with it
+            disk::remove(&self.base, &uuid).await?;
+            self.remove_element(&uuid);
+        }
+
+        Ok(())
+    }
+
+    /// A random UUID that isn't used by any element yet.
+    fn new_uuid(&self) -> Uuid {
+        loop {
+            let uuid = Uuid::new_v4();
+            if !self.elements.contains_key(&uuid) {
+                return uuid;
+            }
+        }
+    }
+
+    /// The elements pinned to the favorites, leaving out trashed ones.
+    pub fn pinned(&self) -> Vec<(Uuid, Arc<Element>)> {
+        let pinned: Vec<_> = self
+            .elements
             .iter()
-            .filter(|e| e.pinned)
-            .map(|e| e.value().clone())
+            .filter(|e| e.pinned && !e.is_deleted())
+            .map(|e| (*e.key(), e.value().clone()))
+            .collect();
+
+        // only once `elements` isn't locked, as finding ancestors locks it again
+        pinned
+            .into_iter()
+            .filter(|(uuid, _)| !self.is_trashed(uuid))
             .collect()
     }
 
-    pub async fn trash(&self) -> Vec<Arc<Element>> {
-        self.elements
-            .iter()
-            .filter(|e| e.parent == Parent::Trash)
-            .map(|e| e.value().clone())
+    /// The elements directly in the trash.
+    pub fn trash(&self) -> Vec<(Uuid, Arc<Element>)> {
+        self.children(Parent::Trash)
+            .into_iter()
+            .filter(|(_, e)| !e.is_deleted())
             .collect()
     }
 
-    pub async fn move_elements(
+    /// Copy the element `uuid` into `parent` as `name`, along with everything in it for
+    /// folders unless `recursive` is false. Returns the UUID of the copy.
+    pub async fn copy_element(
         &self,
-        element_path: impl AsRef<Path>,
-        target_path: impl AsRef<Path>,
-    ) -> eyre::Result<()> {
-        let element = element_path
-            .as_ref()
-            .strip_prefix("/")
-            .unwrap_or(element_path.as_ref());
-        let target_path = target_path
-            .as_ref()
-            .strip_prefix("/")
-            .unwrap_or(target_path.as_ref());
-
-        let Some((element_uuid, _)) = self.uuid_from_path(&element) else {
-            return Err(eyre::eyre!("{element:?} not found"));
+        uuid: Uuid,
+        parent: Parent,
+        name: &str,
+        recursive: bool,
+    ) -> eyre::Result<Uuid> {
+        let Some(element) = self.elements.get(&uuid).map(|e| e.value().clone()) else {
+            return Err(eyre::eyre!("{uuid} not found"));
         };
 
-        let target_parent = if target_path == Path::new("") {
-            Parent::Root
-        } else {
-            let Some((uuid, _)) = self.uuid_from_path(&target_path) else {
-                return Err(eyre::eyre!("{target_path:?} not found"));
-            };
+        if let Parent::Directory(folder) = parent {
+            if folder == uuid || self.is_within(&folder, &uuid) {
+                return Err(eyre::eyre!("can't copy {:?} into itself", element.name));
+            }
+        }
 
-            Parent::Directory(uuid)
-        };
+        let copy = self.new_uuid();
+        disk::copy(&self.base, &uuid, &copy, parent, name).await?;
+        self.update_element(copy).await?;
+
+        if !recursive {
+            return Ok(copy);
+        }
+
+        // the copy of each folder, to put the copies of what's in it into
+        let mut folders = vec![(uuid, copy)];
+        while let Some((folder, folder_copy)) = folders.pop() {
+            let children = self.children(Parent::Directory(folder));
+
+            for (child, element) in children.into_iter().filter(|(_, e)| !e.is_deleted()) {
+                let child_copy = self.new_uuid();
+                let parent = Parent::Directory(folder_copy);
+                disk::copy(&self.base, &child, &child_copy, parent, &element.name).await?;
+                self.update_element(child_copy).await?;
+
+                if element.is_dir() {
+                    folders.push((child, child_copy));
+                }
+            }
+        }
+
+        Ok(copy)
+    }
 
-        if let Err(err) = disk::change_parent(&self.base, &element_uuid, target_parent).await {
-            return Err(eyre::eyre!("failed to delete {element:?}: {err}"));
+    /// Rename the element `uuid` to `name`, leaving it where it is.
+    pub async fn rename(&self, uuid: Uuid, name: &str) -> eyre::Result<()> {
+        disk::rename(&self.base, &uuid, name).await?;
+        self.update_element(uuid).await
+    }
+
+    /// Pin the element `uuid` to the favorites, or unpin it.
+    pub async fn set_pinned(&self, uuid: Uuid, pinned: bool) -> eyre::Result<()> {
+        disk::set_pinned(&self.base, &uuid, pinned).await?;
+        self.update_element(uuid).await
+    }
+
+    /// Replace the tags on the document `uuid`.
+    pub async fn set_tags(&self, uuid: Uuid, tags: &[String]) -> eyre::Result<()> {
+        if !self.elements.get(&uuid).is_some_and(|e| e.is_file()) {
+            return Err(eyre::eyre!("only documents can be tagged"));
+        }
+
+        disk::set_tags(&self.base, &uuid, tags).await?;
+        self.update_element(uuid).await
+    }
+
+    /// Move the element `uuid` into `parent`, renaming it to `name`.
+    pub async fn move_element(&self, uuid: Uuid, parent: Parent, name: &str) -> eyre::Result<()> {
+        let Some(element) = self.elements.get(&uuid).map(|e| e.value().clone()) else {
+            return Err(eyre::eyre!("{uuid} not found"));
         };
 
-        if let Err(err) = self.update_element(element_uuid).await {
-            tracing::error!("failed to update element from disk: {err}");
+        if let Parent::Directory(folder) = parent {
+            if folder == uuid || self.is_within(&folder, &uuid) {
+                return Err(eyre::eyre!("can't move {:?} into itself", element.name));
+            }
         }
 
-        Ok(())
+        if element.parent != parent {
+            disk::change_parent(&self.base, &uuid, parent).await?;
+        }
+        if element.name != name {
+            disk::rename(&self.base, &uuid, name).await?;
+        }
+
+        self.update_element(uuid).await
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub format: Format,
+    /// Pages, in order.
+    pub pages: Vec<DocumentPage>,
+    /// The number of pages the tablet last counted, which may lag behind `pages`.
+    pub page_count: Option<u32>,
+    pub orientation: Option<Orientation>,
+    /// Reflowed text settings for PDFs and EPUBs.
+    pub margins: Option<f64>,
+    pub text_scale: Option<f64>,
+    pub font_name: Option<String>,
+    pub line_height: Option<f64>,
+    pub zoom_mode: Option<String>,
+    /// Tags on the whole document.
+    pub tags: Vec<String>,
+    /// Tags on individual pages, by page ID.
+    pub page_tags: Vec<(Uuid, String)>,
+    pub size_in_bytes: Option<u64>,
+    /// The page shown as the document's thumbnail, or `None` for the last opened page.
+    pub cover_page: Option<u32>,
+    /// Title, authors and so on, as extracted from PDFs and EPUBs.
+    pub document_metadata: serde_json::Map<String, serde_json::Value>,
+    /// The tablet's own settings, like the last tool used.
+    pub extra_metadata: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A page of a [`Document`].
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct Document {
-    format: Format,
+pub struct DocumentPage {
+    pub id: Uuid,
+    /// The page (starting from zero) of the original PDF or EPUB shown under this page's
+    /// annotations, or `None` for pages inserted on the tablet.
+    pub redirect: Option<u32>,
+    /// The fractional index the page is sorted by.
+    pub idx: Option<String>,
+    /// The name of the background template, like `"P Grid small"`.
+    pub template: Option<String>,
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub struct Element {
     name: String,
     parent: Parent,
     pinned: bool,
+    created: Option<SystemTime>,
+    last_modified: SystemTime,
+    last_opened: Option<SystemTime>,
+    last_opened_page: Option<u32>,
+    deleted: bool,
+    metadata_modified: bool,
+    modified: bool,
+    synced: bool,
+    version: u32,
     kind: ElementKind,
 }
 
@@ -350,16 +1203,89 @@ impl Element {
             ElementKind::Directory => false,
         }
     }
+
+    /// The document's contents, or `None` for directories.
+    pub fn document(&self) -> Option<&Document> {
+        match &self.kind {
+            ElementKind::Document(document) => Some(document),
+            ElementKind::Directory => None,
+        }
+    }
+
+    /// The format of a document, or `None` for directories.
+    pub fn format(&self) -> Option<Format> {
+        self.document().map(|d| d.format)
+    }
+
+    pub fn created(&self) -> Option<SystemTime> {
+        self.created
+    }
+
+    /// Whether the element is pinned to the favorites.
+    pub fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+    pub fn last_modified(&self) -> SystemTime {
+        self.last_modified
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn last_opened(&self) -> Option<SystemTime> {
+        self.last_opened
+    }
+
+    /// When the element was last used, for sorting by recency.
+    pub fn last_used(&self) -> SystemTime {
+        self.last_opened
+            .map_or(self.last_modified, |opened| opened.max(self.last_modified))
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn last_opened_page(&self) -> Option<u32> {
+        self.last_opened_page
+    }
+
+    /// Whether the element was deleted on the tablet and is only waiting to be removed by a
+    /// sync. Deleted elements are hidden everywhere else.
+    pub fn is_deleted(&self) -> bool {
+        self.deleted
+    }
+
+    /// Whether the element has changes that haven't been synced yet.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn is_modified(&self) -> bool {
+        self.modified || self.metadata_modified
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    /// The number of times the metadata has been changed.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The pages of a document, in order.
+    pub fn pages(&self) -> eyre::Result<&[DocumentPage]> {
+        match self.document() {
+            Some(document) => Ok(&document.pages),
+            None => Err(eyre::eyre!("{:?} is not a document", self.name)),
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 enum ElementKind {
-    Document(Document),
+    Document(Box<Document>),
     Directory,
 }
 
 #[derive(serde::Deserialize, serde::Serialize, Eq, PartialEq, Debug, Copy, Clone)]
-enum Format {
+pub enum Format {
     #[serde(rename = "notebook")]
     Notebook,
     #[serde(rename = "pdf")]
@@ -368,7 +1294,44 @@ enum Format {
     Epub,
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Eq, PartialEq, Debug, Copy, Clone)]
+impl Format {
+    /// The extension documents in this format are served with by default, notebooks being
+    /// drawn into PDFs.
+    pub fn served_extension(self) -> &'static str {
+        match self {
+            Self::Notebook | Self::Pdf => "pdf",
+            Self::Epub => "epub",
+        }
+    }
+}
+
+/// A file an upload is written to, removed once dropped unless it was imported.
+#[derive(Debug)]
+pub struct Upload(PathBuf);
+
+impl Upload {
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Where a trashed element was, kept while it's in the trash.
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Origin {
+    /// The folder it was in.
+    parent: Parent,
+    /// The element that was moved to the trash to trash it, itself or a folder it was in.
+    trashed_with: Uuid,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Eq, PartialEq, Hash, Debug, Copy, Clone)]
 pub enum Parent {
     #[serde(rename = "")]
     Root,
@@ -377,3 +1340,259 @@ pub enum Parent {
     #[serde(untagged)]
     Directory(Uuid),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::TempDocuments;
+
+    #[tokio::test]
+    async fn resolves_paths_through_the_tree() {
+        let docs = TempDocuments::new("paths");
+        let fs = Remarkable::from_path(docs.path()).await;
+
+        let a = fs.create_directory(Parent::Root, "A").await.unwrap();
+        let b = fs.create_directory(Parent::Root, "B").await.unwrap();
+        let a_notes = fs
+            .create_directory(Parent::Directory(a), "Notes")
+            .await
+            .unwrap();
+        let b_notes = fs
+            .create_directory(Parent::Directory(b), "Notes")
+            .await
+            .unwrap();
+
+        let uuid = |path: &str| fs.element(path).map(|(uuid, _)| uuid);
+        assert_eq!(uuid("/A/Notes"), Some(a_notes));
+        assert_eq!(uuid("B/Notes"), Some(b_notes));
+        assert_eq!(uuid("Notes"), None);
+        assert_eq!(uuid("A/Notes/Notes"), None);
+
+        // looked up again from the cache, which has to follow the move
+        fs.move_element(a_notes, Parent::Directory(b), "Old")
+            .await
+            .unwrap();
+        assert_eq!(uuid("A/Notes"), None);
+        assert_eq!(uuid("B/Old"), Some(a_notes));
+        assert_eq!(fs.list("B").await.unwrap().len(), 2);
+        assert!(fs.list("A").await.unwrap().is_empty());
+
+        fs.move_to_trash(b).await.unwrap();
+        assert_eq!(uuid("B/Notes"), None);
+        assert_eq!(uuid("Trash/Notes"), Some(b_notes));
+
+        fs.purge(b_notes).await.unwrap();
+        assert_eq!(uuid("Trash/Notes"), None);
+        assert!(fs
+            .list("Trash")
+            .await
+            .unwrap()
+            .iter()
+            .all(|(u, _, _)| *u != b_notes));
+
+        // a sibling already named like a duplicate keeps its name, and the oldest keeps theirs
+        let mut x = Vec::new();
+        for (created, name) in [(1000, "X"), (2000, "X (2)"), (3000, "X")] {
+            let uuid = fs
+                .create_directory(Parent::Directory(a), name)
+                .await
+                .unwrap();
+            docs.set_created(&uuid, created);
+            fs.update_element(uuid).await.unwrap();
+            x.push(uuid);
+        }
+        let mut names: Vec<_> = fs
+            .list("A")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(u, _, n)| (u, n))
+            .collect();
+        names.sort();
+        let mut expected = vec![
+            (x[0], "X".into()),
+            (x[1], "X (2)".to_string()),
+            (x[2], "X (3)".into()),
+        ];
+        expected.sort();
+        assert_eq!(names, expected);
+        assert_eq!(uuid("A/X (3)"), Some(x[2]));
+    }
+
+    #[tokio::test]
+    async fn finds_lost_elements() {
+        let docs = TempDocuments::new("lost");
+        let base = docs.path();
+        let fs = Remarkable::from_path(base).await;
+
+        let a = fs.create_directory(Parent::Root, "A").await.unwrap();
+        let b = fs
+            .create_directory(Parent::Directory(a), "B")
+            .await
+            .unwrap();
+        // so the cycle is shown from A, the older one
+        docs.set_created(&a, 1000);
+        docs.set_created(&b, 2000);
+        let notes = fs
+            .create_directory(Parent::Directory(b), "Notes")
+            .await
+            .unwrap();
+        let orphan = fs.create_directory(Parent::Root, "Orphan").await.unwrap();
+        assert!(!fs.has_lost());
+
+        // as a sync might leave them
+        disk::change_parent(base, &a, Parent::Directory(b))
+            .await
+            .unwrap();
+        let missing = Uuid::new_v4();
+        disk::change_parent(base, &orphan, Parent::Directory(missing))
+            .await
+            .unwrap();
+        fs.index().await;
+
+        let mut lost: Vec<_> = fs
+            .list(LOST_DIRECTORY)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(_, _, name)| name)
+            .collect();
+        lost.sort();
+        assert_eq!(lost, ["A", "Orphan"]);
+        assert!(fs.list("/").await.unwrap().is_empty());
+
+        // the cycle is broken where it's shown from
+        let uuid = |path: &str| fs.element(path).map(|(uuid, _)| uuid);
+        assert_eq!(uuid("Lost+Found/A/B/Notes"), Some(notes));
+        assert_eq!(uuid("Lost+Found/A/B/A"), None);
+        assert_eq!(uuid("Lost+Found/B"), None);
+
+        // moving them somewhere that exists finds them again
+        fs.move_element(orphan, Parent::Root, "Orphan")
+            .await
+            .unwrap();
+        fs.move_element(a, Parent::Root, "A").await.unwrap();
+        assert!(!fs.has_lost());
+        assert_eq!(uuid("A/B/Notes"), Some(notes));
+        assert_eq!(uuid("Orphan"), Some(orphan));
+    }
+
+    #[tokio::test]
+    async fn reads_everything_in_the_metadata() {
+        let docs = TempDocuments::copy_of("metadata", "samples/v6");
+        let fs = Remarkable::from_path(docs.path()).await;
+
+        let (uuid, tester) = fs.element("Tester").unwrap();
+        let opened = SystemTime::UNIX_EPOCH + Duration::from_millis(1711492382709);
+        assert_eq!(tester.last_opened(), Some(opened));
+        assert_eq!(tester.last_opened_page(), Some(0));
+        assert!(!tester.is_modified() && !tester.is_synced());
+        assert_eq!(tester.version(), 0);
+
+        // as a sync leaves it
+        let path = docs
+            .path()
+            .join(format!("{uuid}.{}", disk::METADATA_EXTENSION));
+        let mut metadata: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        metadata["metadatamodified"] = true.into();
+        metadata["synced"] = true.into();
+        metadata["version"] = 3.into();
+        std::fs::write(path, metadata.to_string()).unwrap();
+        fs.update_element(uuid).await.unwrap();
+
+        let (_, tester) = fs.element("Tester").unwrap();
+        assert!(tester.is_modified() && tester.is_synced());
+        assert_eq!(tester.version(), 3);
+    }
+
+    #[tokio::test]
+    async fn restores_what_was_trashed_along() {
+        let docs = TempDocuments::new("restore");
+        let base = docs.path();
+        let fs = Remarkable::from_path(base).await;
+        let uuid = |path: &str| fs.element(path).map(|(uuid, _)| uuid);
+
+        let a = fs.create_directory(Parent::Root, "A").await.unwrap();
+        let inner = fs
+            .create_directory(Parent::Directory(a), "Inner")
+            .await
+            .unwrap();
+        let notes = fs
+            .create_directory(Parent::Directory(inner), "Notes")
+            .await
+            .unwrap();
+        let old = fs
+            .create_directory(Parent::Directory(a), "Old")
+            .await
+            .unwrap();
+
+        // trashed on its own before the folder it was in, so it stays there
+        fs.move_to_trash(old).await.unwrap();
+        fs.move_to_trash(a).await.unwrap();
+        fs.restore(a, Parent::Root, "A").await.unwrap();
+        assert_eq!(uuid("A/Inner/Notes"), Some(notes));
+        assert_eq!(uuid("Trash/Old"), Some(old));
+
+        // which takes nothing else with it when deleted for good
+        fs.move_to_trash(a).await.unwrap();
+        fs.purge(old).await.unwrap();
+        assert_eq!(fs.trash().len(), 3);
+        fs.purge(a).await.unwrap();
+        assert!(fs.trash().is_empty());
+
+        // the tablet keeps no origin, so those only come out where they're put
+        let b = fs.create_directory(Parent::Root, "B").await.unwrap();
+        let c = fs
+            .create_directory(Parent::Directory(b), "C")
+            .await
+            .unwrap();
+        for uuid in [b, c] {
+            disk::change_parent(base, &uuid, Parent::Trash)
+                .await
+                .unwrap();
+            fs.update_element(uuid).await.unwrap();
+        }
+        let d = fs.create_directory(Parent::Root, "D").await.unwrap();
+        fs.restore(b, Parent::Directory(d), "B").await.unwrap();
+        assert_eq!(uuid("D/B"), Some(b));
+        assert_eq!(uuid("Trash/C"), Some(c));
+        fs.restore(c, Parent::Root, "C").await.unwrap();
+        assert_eq!(uuid("C"), Some(c));
+
+        // and nothing of it is left behind once everything is out of the trash
+        let origins = std::fs::read_dir(base)
+            .unwrap()
+            .filter(|entry| {
+                let path = entry.as_ref().unwrap().path();
+                path.extension().is_some_and(|e| e == ORIGIN_EXTENSION)
+            })
+            .count();
+        assert_eq!(origins, 0);
+    }
+
+    #[tokio::test]
+    async fn shows_folders_named_like_virtual_ones_apart() {
+        let docs = TempDocuments::new("virtual-names");
+        let fs = Remarkable::from_path(docs.path()).await;
+
+        let trash = fs.create_directory(Parent::Root, "Trash").await.unwrap();
+        let favorites = fs
+            .create_directory(Parent::Root, "Favorites")
+            .await
+            .unwrap();
+        let notes = fs
+            .create_directory(Parent::Directory(trash), "Notes")
+            .await
+            .unwrap();
+        fs.set_pinned(notes, true).await.unwrap();
+
+        let uuid = |path: &str| fs.element(path).map(|(uuid, _)| uuid);
+        assert_eq!(uuid("Trash (2)"), Some(trash));
+        assert_eq!(uuid("Favorites (2)"), Some(favorites));
+        assert_eq!(uuid("Trash (2)/Notes"), Some(notes));
+        assert_eq!(uuid("Favorites/Notes"), Some(notes));
+        assert_eq!(uuid("Trash/Notes"), None);
+        assert_eq!(fs.path_of(&notes), Some(PathBuf::from("Trash (2)/Notes")));
+    }
+}
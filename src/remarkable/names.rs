@@ -0,0 +1,177 @@
+//! How element names are shown in paths.
+//!
+//! The tablet allows any name, but characters like `/` and `:` or a trailing dot break paths
+//! on Windows and most cloud storage. Those are shown as lookalikes instead, like `／` for
+//! `/`, the way rclone encodes them. Lookalikes that were in the name to begin with are
+//! quoted with `‛`, so every name can be told back from its path exactly. An empty name is
+//! shown as `‛` alone, which no other name is shown as.
+
+/// Marks the character after it as part of the name, rather than standing for another.
+const QUOTE: char = '‛';
+
+/// Characters that aren't allowed anywhere in a name, and the lookalikes shown instead.
+const REPLACED: [(char, char); 9] = [
+    ('/', '／'),
+    ('\\', '＼'),
+    (':', '：'),
+    ('*', '＊'),
+    ('?', '？'),
+    ('"', '＂'),
+    ('<', '＜'),
+    ('>', '＞'),
+    ('|', '｜'),
+];
+
+/// Shown instead of a dot ending a name, which Windows drops.
+const DOT: (char, char) = ('.', '．');
+
+/// Shown instead of a space starting or ending a name, which is often trimmed.
+const SPACE: (char, char) = (' ', '␠');
+
+/// Control characters are shown as their symbols, from `␀` up.
+const CONTROL_SYMBOLS: u32 = 0x2400;
+const DELETE: (char, char) = ('\x7f', '␡');
+
+/// The name `name` is shown with in paths.
+pub fn encode(name: &str) -> String {
+    if name.is_empty() {
+        return QUOTE.to_string();
+    }
+
+    let last = name.chars().count().saturating_sub(1);
+
+    name.chars()
+        .enumerate()
+        .flat_map(|(i, c)| {
+            let replaced = match c {
+                c if c == DOT.0 && i == last => Some(DOT.1),
+                c if c == SPACE.0 && (i == 0 || i == last) => Some(SPACE.1),
+                c if c == DELETE.0 => Some(DELETE.1),
+                c if c.is_ascii_control() => char::from_u32(CONTROL_SYMBOLS + u32::from(c)),
+                c => REPLACED
+                    .iter()
+                    .find(|(from, _)| *from == c)
+                    .map(|(_, to)| *to),
+            };
+
+            match replaced {
+                Some(to) => [None, Some(to)],
+                None if c == QUOTE || original(c).is_some() => [Some(QUOTE), Some(c)],
+                None => [None, Some(c)],
+            }
+        })
+        .flatten()
+        .collect()
+}
+
+/// The name shown as `segment` in a path, the reverse of [`encode`].
+pub fn decode(segment: &str) -> String {
+    if segment.chars().eq([QUOTE]) {
+        return String::new();
+    }
+
+    let mut name = String::with_capacity(segment.len());
+    let mut chars = segment.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            QUOTE => name.push(chars.next().unwrap_or(QUOTE)),
+            c => name.push(original(c).unwrap_or(c)),
+        }
+    }
+
+    name
+}
+
+/// The character `c` stands for, if it's a lookalike.
+fn original(c: char) -> Option<char> {
+    match c {
+        c if c == DOT.1 => Some(DOT.0),
+        c if c == SPACE.1 => Some(SPACE.0),
+        c if c == DELETE.1 => Some(DELETE.0),
+        c => match u32::from(c).checked_sub(CONTROL_SYMBOLS) {
+            Some(control) if control < 0x20 => char::from_u32(control),
+            _ => REPLACED
+                .iter()
+                .find(|(_, to)| *to == c)
+                .map(|(from, _)| *from),
+        },
+    }
+}
+
+/// The name an element named `name`, shown as `source`, gets when it's put at a path ending
+/// in `shown`.
+///
+/// That's `shown` as decoded, unless it's what the element was shown as already, like
+/// `Notes (2)` for one of two `Notes`, which only tells it apart from a sibling, in which case
+/// it keeps its name.
+pub fn from_path(shown: &str, source: &str, name: &str) -> String {
+    match shown == source {
+        true => name.to_string(),
+        false => decode(shown),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrips_names() {
+        let names = [
+            "Notes",
+            "a/b\\c:d*e?f\"g<h>i|j",
+            "Ends with a dot.",
+            "...",
+            " padded ",
+            "tab\there\x7f",
+            "already／shown．",
+            "‛quoted‛",
+            "␠",
+            "",
+        ];
+
+        for name in names {
+            let shown = encode(name);
+            assert_eq!(decode(&shown), name, "{shown}");
+
+            assert!(!shown.is_empty());
+            assert!(!shown.contains(|c: char| c.is_ascii_control()), "{shown}");
+            assert!(!shown.contains(['/', '\\', ':', '*', '?', '"', '<', '>', '|']));
+            assert!(
+                !shown.ends_with(['.', ' ']) && !shown.starts_with(' '),
+                "{shown}"
+            );
+        }
+
+        assert_eq!(encode("a/b."), "a／b．");
+        assert_eq!(encode("a／b"), "a‛／b");
+        assert_eq!(encode(""), "‛");
+        assert_eq!(encode("‛"), "‛‛");
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn roundtrips_names_with_lookalikes(
+            name in r#"([ .‛／＼：＊？＂＜＞｜．␀-␡\x00-\x1f\x7f/\\:*?"<>|]|\PC){0,12}"#
+        ) {
+            let shown = encode(&name);
+            proptest::prop_assert_eq!(decode(&shown), name);
+            proptest::prop_assert!(!shown.is_empty());
+            proptest::prop_assert!(!shown.contains(|c: char| c.is_ascii_control()));
+            proptest::prop_assert!(!shown.ends_with(['.', ' ']) && !shown.starts_with(' '));
+        }
+    }
+
+    #[test]
+    fn takes_names_back_from_paths() {
+        assert_eq!(from_path("Notes (2)", "Notes (2)", "Notes"), "Notes");
+        assert_eq!(from_path("Notes", "Notes", "Notes"), "Notes");
+        assert_eq!(from_path("a／b (2)", "a／b (2)", "a/b"), "a/b");
+        assert_eq!(from_path("Notes (2)", "Notes", "Notes"), "Notes (2)");
+        assert_eq!(from_path("Notes", "Notes (2)", "Notes"), "Notes");
+        assert_eq!(from_path("Other (2)", "Notes", "Notes"), "Other (2)");
+        assert_eq!(from_path("Other：2", "Notes", "Notes"), "Other:2");
+        assert_eq!(from_path("‛：", "Notes", "Notes"), "：");
+    }
+}
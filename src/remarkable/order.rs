@@ -0,0 +1,167 @@
+//! Ordering of pages by the fractional indices in `.content` files.
+//!
+//! Each page has a string `idx` (like `"ba"`) and pages are shown in the lexicographic order
+//! of those strings. Inserting a page only needs a new string that sorts between its
+//! neighbours', so no other page has to be renumbered.
+
+use std::cmp::Ordering;
+
+use color_eyre::eyre;
+
+use super::lines::CrdtId;
+
+/// The characters indices are made of, in order. Each acts as a digit of a base-26 fraction.
+const DIGITS: std::ops::RangeInclusive<u8> = b'a'..=b'z';
+
+/// Sort `items` by their index, given by `key` as the index and the timestamp it was set at.
+///
+/// Items with the same index are ordered by timestamp, and items without one go last. The
+/// sort is stable, so anything else keeps its original order.
+pub fn sort_by_index<T>(items: &mut [T], key: impl Fn(&T) -> Option<(&str, &str)>) {
+    items.sort_by(|a, b| match (key(a), key(b)) {
+        (Some((a, a_time)), Some((b, b_time))) => a.cmp(b).then_with(|| {
+            let time = |t: &str| t.parse::<CrdtId>().unwrap_or_default();
+            time(a_time).cmp(&time(b_time))
+        }),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+}
+
+/// Generate an index that sorts after `before` and before `after`, where `None` stands for
+/// either end of the document.
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn between(before: Option<&str>, after: Option<&str>) -> eyre::Result<String> {
+    let before = before.unwrap_or_default();
+
+    for index in [Some(before), after].into_iter().flatten() {
+        if !index.bytes().all(|b| DIGITS.contains(&b)) {
+            return Err(eyre::eyre!("invalid page index {index:?}"));
+        }
+    }
+
+    if after.is_some_and(|after| after <= before) {
+        return Err(eyre::eyre!("page index {before:?} isn't before {after:?}"));
+    }
+
+    let index = midpoint(before.as_bytes(), after.map(str::as_bytes))
+        .and_then(|index| String::from_utf8(index).ok())
+        .filter(|index| {
+            index.as_str() > before && after.is_none_or(|after| index.as_str() < after)
+        });
+
+    match index {
+        Some(index) => Ok(index),
+        None => Err(eyre::eyre!(
+            "no page index between {before:?} and {after:?}"
+        )),
+    }
+}
+
+/// The shortest string between `a` and `b`, treating both as base-26 fractions.
+fn midpoint(a: &[u8], b: Option<&[u8]>) -> Option<Vec<u8>> {
+    let zero = *DIGITS.start();
+
+    if let Some(b) = b {
+        // keep the prefix they share, padding `a` with zeros
+        let shared = b
+            .iter()
+            .enumerate()
+            .take_while(|(i, digit)| a.get(*i).copied().unwrap_or(zero) == **digit)
+            .count();
+
+        if shared == b.len() {
+            // `b` is `a` with zeros removed, so there's nothing between them
+            return None;
+        }
+
+        if shared > 0 {
+            let rest = midpoint(a.get(shared..).unwrap_or_default(), Some(&b[shared..]))?;
+            return Some([&b[..shared], &rest].concat());
+        }
+    }
+
+    let digit_a = a.first().map_or(0, |d| d - zero);
+    let digit_b = b.map_or(DIGITS.len() as u8, |b| b[0] - zero);
+
+    if digit_b - digit_a > 1 {
+        return Some(vec![zero + (digit_a + digit_b).div_ceil(2)]);
+    }
+
+    match b {
+        // `b` without its tail is already bigger than `a`
+        Some(b) if b.len() > 1 => Some(vec![b[0]]),
+        _ => {
+            let rest = midpoint(a.get(1..).unwrap_or_default(), None)?;
+            Some([&[zero + digit_a][..], &rest].concat())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts_by_index_then_timestamp() {
+        let mut pages = vec![
+            ("c", Some(("bb", "1:2"))),
+            ("none", None),
+            ("b", Some(("ba", "2:1"))),
+            ("a", Some(("ba", "1:9"))),
+            ("d", Some(("bba", "1:1"))),
+        ];
+        sort_by_index(&mut pages, |(_, key)| *key);
+
+        let names: Vec<_> = pages.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["a", "b", "c", "d", "none"]);
+    }
+
+    #[test]
+    fn generates_indices_between() {
+        let cases = [
+            (None, None),
+            (Some("ba"), None),
+            (None, Some("ba")),
+            (Some("ba"), Some("bb")),
+            (Some("ba"), Some("bab")),
+            (Some("az"), Some("b")),
+            (Some("zz"), None),
+            (Some("b"), Some("bab")),
+            (None, Some("aab")),
+        ];
+
+        for (before, after) in cases {
+            let index = between(before, after).unwrap();
+
+            assert!(
+                index.as_str() > before.unwrap_or_default(),
+                "{index} {before:?}"
+            );
+            assert!(
+                after.is_none_or(|a| index.as_str() < a),
+                "{index} {after:?}"
+            );
+            assert!(!index.ends_with('a'), "{index}");
+        }
+    }
+
+    #[test]
+    fn keeps_inserting() {
+        let (mut low, high) = ("ba".to_string(), "bb".to_string());
+
+        for _ in 0..100 {
+            let index = between(Some(&low), Some(&high)).unwrap();
+            assert!(low < index && index < high);
+            low = index;
+        }
+    }
+
+    #[test]
+    fn rejects_impossible_ranges() {
+        assert!(between(Some("b"), Some("ba")).is_err());
+        assert!(between(Some("bb"), Some("ba")).is_err());
+        assert!(between(Some("B"), None).is_err());
+    }
+}
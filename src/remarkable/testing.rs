@@ -0,0 +1,65 @@
+//! Document directories for tests that change what's in them.
+
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+
+use super::disk::METADATA_EXTENSION;
+
+/// An empty document directory, removed along with everything in it once dropped.
+pub struct TempDocuments(PathBuf);
+
+impl TempDocuments {
+    /// An empty document directory, unique to `test`.
+    pub fn new(test: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("rm-webdav-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        Self(dir)
+    }
+
+    /// A directory holding a copy of every file in `samples`.
+    pub fn copy_of(test: &str, samples: impl AsRef<Path>) -> Self {
+        let documents = Self::new(test);
+        copy_dir(samples.as_ref(), documents.path());
+
+        documents
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Set when the element `uuid` was created and last modified to `millis` since the unix
+    /// epoch, so tests don't depend on how fast they run. It needs to be read again after.
+    pub fn set_created(&self, uuid: &Uuid, millis: u64) {
+        let path = self.0.join(format!("{uuid}.{METADATA_EXTENSION}"));
+        let mut metadata: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+
+        metadata["createdTime"] = millis.to_string().into();
+        metadata["lastModified"] = millis.to_string().into();
+        std::fs::write(path, metadata.to_string()).unwrap();
+    }
+}
+
+impl Drop for TempDocuments {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    for entry in std::fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let target = to.join(entry.file_name());
+
+        if entry.file_type().unwrap().is_dir() {
+            std::fs::create_dir_all(&target).unwrap();
+            copy_dir(&entry.path(), &target);
+        } else {
+            std::fs::copy(entry.path(), target).unwrap();
+        }
+    }
+}
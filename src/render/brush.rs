@@ -0,0 +1,288 @@
+//! How each writing tool turns the points of a [`Line`] into paint.
+//!
+//! Tools with a uniform look (fineliner, highlighter, shader) become a single polyline. The
+//! others vary along the stroke with the pen's pressure, speed and direction, so they're drawn
+//! as one short stroke per segment, styled by the point it starts at. The formulas are tuned by
+//! eye against the tablet's own rendering.
+
+use std::f32::consts::TAU;
+
+use crate::remarkable::lines::{Line, Pen, Point};
+
+use super::{rgb, to_canvas};
+
+/// Strokes are never drawn thinner than this, in pixels.
+const MIN_WIDTH: f32 = 0.5;
+
+/// A polyline drawn with a single style, in canvas coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stroke {
+    pub points: Vec<(f32, f32)>,
+    pub width: f32,
+    pub color: [u8; 3],
+    /// From 0 (invisible) to 1 (opaque).
+    pub opacity: f32,
+    pub cap: Cap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cap {
+    Round,
+    Square,
+}
+
+/// The brush a tool paints with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Brush {
+    Ballpoint,
+    Fineliner,
+    Pencil,
+    MechanicalPencil,
+    Paintbrush,
+    Calligraphy,
+    Marker,
+    Highlighter,
+    Shader,
+}
+
+impl Brush {
+    /// The brush for `tool`, or `None` for erasers, which leave nothing behind.
+    pub fn new(tool: Pen) -> Option<Self> {
+        Some(match tool {
+            Pen::Ballpoint1 | Pen::Ballpoint2 => Self::Ballpoint,
+            Pen::Fineliner1 | Pen::Fineliner2 => Self::Fineliner,
+            Pen::Pencil1 | Pen::Pencil2 => Self::Pencil,
+            Pen::MechanicalPencil1 | Pen::MechanicalPencil2 => Self::MechanicalPencil,
+            Pen::Paintbrush1 | Pen::Paintbrush2 => Self::Paintbrush,
+            Pen::Calligraphy => Self::Calligraphy,
+            Pen::Marker1 | Pen::Marker2 => Self::Marker,
+            Pen::Highlighter1 | Pen::Highlighter2 => Self::Highlighter,
+            Pen::Shader => Self::Shader,
+            Pen::Eraser | Pen::EraserArea => return None,
+            Pen::Unknown(id) => {
+                tracing::debug!("drawing unknown tool {id} as a fineliner");
+                Self::Fineliner
+            }
+        })
+    }
+
+    /// Whether the brush looks the same along the whole stroke.
+    fn is_uniform(self) -> bool {
+        matches!(self, Self::Fineliner | Self::Highlighter | Self::Shader)
+    }
+
+    fn cap(self) -> Cap {
+        match self {
+            Self::Highlighter => Cap::Square,
+            _ => Cap::Round,
+        }
+    }
+
+    /// The width, color and opacity of the stroke starting at `point`, drawn with the tool
+    /// set to `size`.
+    fn style(
+        self,
+        point: &Point,
+        size: f32,
+        last_width: f32,
+        color: [u8; 3],
+    ) -> (f32, [u8; 3], f32) {
+        let width = f32::from(point.width) / 4.0;
+        let pressure = f32::from(point.pressure) / 255.0;
+        let speed = f32::from(point.speed) / 4.0;
+        let tilt = f32::from(point.direction) * TAU / 255.0;
+
+        match self {
+            Self::Ballpoint => {
+                let intensity = (1.2 * pressure + 0.5 - 0.1 * speed / 35.0).clamp(0.0, 1.0);
+                (
+                    0.5 + pressure + width - 0.5 * speed / 50.0,
+                    lighten(color, (1.0 - intensity).min(0.25)),
+                    1.0,
+                )
+            }
+            Self::Pencil => {
+                let opacity = (pressure - 0.1 * speed / 35.0).clamp(0.0, 1.0) - 0.1;
+                (
+                    0.7 * ((0.8 * size + 0.5 * pressure) * width
+                        - 0.25 * tilt
+                        - 0.6 * speed / 50.0),
+                    color,
+                    opacity.max(0.05),
+                )
+            }
+            Self::MechanicalPencil => {
+                let opacity = (0.8 * pressure - 0.1 * speed / 35.0).clamp(0.0, 1.0);
+                (width, color, (opacity * opacity).max(0.05))
+            }
+            Self::Paintbrush => {
+                let intensity = (pressure.powf(1.5) - 0.2 * speed / 50.0).clamp(0.0, 1.0);
+                (
+                    0.7 * ((1.0 + 1.4 * pressure) * width - 0.5 * tilt - speed / 50.0),
+                    lighten(color, 1.0 - intensity),
+                    1.0,
+                )
+            }
+            Self::Calligraphy => (
+                0.9 * ((1.0 + pressure) * width - 0.3 * tilt) + 0.1 * last_width,
+                color,
+                1.0,
+            ),
+            Self::Marker => (0.9 * (width - 0.4 * tilt) + 0.1 * last_width, color, 1.0),
+            Self::Fineliner => (width, color, 1.0),
+            Self::Highlighter => (width, color, 0.3),
+            Self::Shader => (width, color, 0.1),
+        }
+    }
+}
+
+/// The strokes to draw for `line`, in order. Erased lines produce none.
+pub fn strokes(line: &Line) -> Vec<Stroke> {
+    let Some(brush) = Brush::new(line.tool) else {
+        return Vec::new();
+    };
+    if line.points.is_empty() {
+        return Vec::new();
+    }
+
+    let color = rgb(line.color);
+    // the size picked for the tool; points' widths already grow with it, but pencil lead
+    // spreads further on top of that
+    let size = match line.thickness_scale as f32 {
+        size if size > 0.0 && size.is_finite() => size,
+        _ => 1.0,
+    };
+    let canvas = |p: &Point| to_canvas(p.x, p.y);
+
+    // a lone point is drawn as a dot by its cap
+    let points = match line.points.len() {
+        1 => vec![line.points[0]; 2],
+        _ => line.points.clone(),
+    };
+
+    if brush.is_uniform() {
+        let width =
+            points.iter().map(|p| f32::from(p.width) / 4.0).sum::<f32>() / points.len() as f32;
+        let (_, color, opacity) = brush.style(&points[0], size, width, color);
+
+        return vec![Stroke {
+            points: points.iter().map(canvas).collect(),
+            width: width.max(MIN_WIDTH),
+            color,
+            opacity,
+            cap: brush.cap(),
+        }];
+    }
+
+    let mut last_width = f32::from(points[0].width) / 4.0;
+
+    points
+        .windows(2)
+        .map(|pair| {
+            let (width, color, opacity) = brush.style(&pair[0], size, last_width, color);
+            last_width = width.max(MIN_WIDTH);
+
+            Stroke {
+                points: pair.iter().map(canvas).collect(),
+                width: last_width,
+                color,
+                opacity: opacity.clamp(0.0, 1.0),
+                cap: brush.cap(),
+            }
+        })
+        .collect()
+}
+
+/// Mix `color` with white, `amount` being the fraction of white.
+fn lighten(color: [u8; 3], amount: f32) -> [u8; 3] {
+    color.map(|c| (f32::from(c) + (255.0 - f32::from(c)) * amount).round() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remarkable::lines::{CrdtId, PenColor};
+
+    fn line(tool: Pen, points: usize) -> Line {
+        Line {
+            tool,
+            color: PenColor::Black,
+            thickness_scale: 2.0,
+            starting_length: 0.0,
+            points: (0..points)
+                .map(|i| Point::from_floats(i as f32, 0.0, 0.0, 0.0, 2.0, 0.5))
+                .collect(),
+            timestamp: CrdtId::default(),
+            move_id: None,
+        }
+    }
+
+    #[test]
+    fn erasers_leave_nothing() {
+        assert!(strokes(&line(Pen::Eraser, 4)).is_empty());
+        assert!(strokes(&line(Pen::EraserArea, 4)).is_empty());
+    }
+
+    #[test]
+    fn uniform_tools_are_one_polyline() {
+        let fineliner = strokes(&line(Pen::Fineliner2, 4));
+        assert_eq!(fineliner.len(), 1);
+        assert_eq!(fineliner[0].points.len(), 4);
+        assert_eq!(fineliner[0].opacity, 1.0);
+
+        let highlighter = strokes(&line(Pen::Highlighter2, 4));
+        assert_eq!(highlighter[0].cap, Cap::Square);
+        assert!(highlighter[0].opacity < 1.0);
+    }
+
+    #[test]
+    fn varying_tools_are_segmented() {
+        for tool in [
+            Pen::Ballpoint2,
+            Pen::Pencil2,
+            Pen::Calligraphy,
+            Pen::Paintbrush2,
+        ] {
+            let strokes = strokes(&line(tool, 4));
+            assert_eq!(strokes.len(), 3, "{tool:?}");
+            assert!(strokes.iter().all(|s| s.width >= MIN_WIDTH));
+        }
+    }
+
+    #[test]
+    fn pencils_widen_with_the_tool_size() {
+        let widths = |size: f64| {
+            let mut line = line(Pen::Pencil2, 4);
+            line.thickness_scale = size;
+            strokes(&line).iter().map(|s| s.width).collect::<Vec<_>>()
+        };
+
+        for (thin, thick) in widths(1.0).into_iter().zip(widths(3.0)) {
+            assert!(thick > thin * 1.5, "{thin} {thick}");
+        }
+    }
+
+    #[test]
+    fn pencils_get_darker_with_pressure() {
+        for tool in [Pen::Pencil2, Pen::MechanicalPencil2] {
+            let opacity = |pressure: f32| {
+                let mut line = line(tool, 2);
+                for point in &mut line.points {
+                    *point = Point::from_floats(point.x, point.y, 0.0, 0.0, 2.0, pressure);
+                }
+                strokes(&line)[0].opacity
+            };
+
+            assert!(opacity(0.2) < opacity(0.5), "{tool:?}");
+            assert!(opacity(0.5) < opacity(0.9), "{tool:?}");
+            assert!(opacity(1.0) <= 1.0 && opacity(0.0) > 0.0, "{tool:?}");
+        }
+    }
+
+    #[test]
+    fn single_points_are_dots() {
+        let strokes = strokes(&line(Pen::Ballpoint2, 1));
+        assert_eq!(strokes.len(), 1);
+        assert_eq!(strokes[0].points[0], strokes[0].points[1]);
+    }
+}
@@ -0,0 +1,38 @@
+//! Rendering of parsed [`Page`](crate::remarkable::lines::Page)s into formats other programs can display.
+
+pub mod brush;
+pub mod pdf;
+pub mod png;
+pub mod svg;
+
+use crate::remarkable::lines::{PenColor, PAGE_HEIGHT, PAGE_WIDTH};
+
+/// Page size in pixels, shared by every backend.
+pub const WIDTH: f32 = PAGE_WIDTH;
+pub const HEIGHT: f32 = PAGE_HEIGHT;
+
+/// Move a point from page coordinates (origin at the top center) to the canvas (origin at the top left).
+pub fn to_canvas(x: f32, y: f32) -> (f32, f32) {
+    (x + WIDTH / 2.0, y)
+}
+
+/// The RGB value each pen color is displayed as.
+pub fn rgb(color: PenColor) -> [u8; 3] {
+    match color {
+        PenColor::Black => [0, 0, 0],
+        PenColor::Gray => [144, 144, 144],
+        PenColor::White => [255, 255, 255],
+        PenColor::Yellow => [251, 247, 25],
+        PenColor::Green => [0, 255, 0],
+        PenColor::Pink => [255, 192, 203],
+        PenColor::Blue => [78, 105, 201],
+        PenColor::Red => [179, 62, 57],
+        PenColor::GrayOverlap => [125, 125, 125],
+        PenColor::Highlight => [255, 237, 117],
+        PenColor::Green2 => [161, 216, 125],
+        PenColor::Cyan => [139, 208, 229],
+        PenColor::Magenta => [183, 130, 205],
+        PenColor::Yellow2 => [247, 232, 81],
+        PenColor::Unknown(_) => [0, 0, 0],
+    }
+}
@@ -0,0 +1,426 @@
+//! PDF output, either one page per notebook page or annotations drawn over an existing PDF.
+
+use std::collections::{BTreeSet, HashSet};
+
+use color_eyre::eyre;
+use lopdf::{
+    content::{Content, Operation},
+    dictionary, Dictionary, Document, Object, ObjectId, Stream,
+};
+
+use crate::remarkable::lines::Page;
+
+use super::{
+    brush::{self, Cap, Stroke},
+    HEIGHT, WIDTH,
+};
+
+pub const CONTENT_TYPE: &str = "application/pdf";
+
+/// Points per pixel, the tablet's screen being 227 DPI.
+const SCALE: f32 = 72.0 / 227.0;
+
+/// Render pages as a single PDF document, with the tablet's physical page size.
+pub fn render(pages: &[Page]) -> eyre::Result<Vec<u8>> {
+    let mut doc = Document::with_version("1.7");
+    let pages_id = doc.new_object_id();
+
+    let kids = pages
+        .iter()
+        .map(|page| {
+            let (content, opacities) = page_content(page, NATIVE_TRANSFORM);
+            let content = doc.add_object(Stream::new(dictionary! {}, content.encode()?));
+
+            Ok(doc
+                .add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "Contents" => content,
+                    "Resources" => dictionary! {
+                        "ExtGState" => graphics_states(&opacities),
+                    },
+                })
+                .into())
+        })
+        .collect::<eyre::Result<Vec<Object>>>()?;
+
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Count" => kids.len() as i64,
+            "Kids" => kids,
+            "MediaBox" => vec![0.into(), 0.into(), (WIDTH * SCALE).into(), (HEIGHT * SCALE).into()],
+        }),
+    );
+
+    let catalog = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog);
+    doc.compress();
+
+    let mut out = Vec::new();
+    doc.save_to(&mut out)?;
+
+    Ok(out)
+}
+
+/// Draw `pages` over an existing PDF, laid out like on the tablet.
+///
+/// Each page is paired with the page of `original` (starting from zero) it annotates, or
+/// `None` for blank pages inserted on the tablet. Pages of the original that aren't shown
+/// are left out.
+pub fn annotate(original: &[u8], pages: &[(Option<u32>, Page)]) -> eyre::Result<Vec<u8>> {
+    let mut doc = Document::load_mem(original)?;
+    let pages_id = doc.catalog()?.get(b"Pages")?.as_reference()?;
+    let originals = doc.get_pages();
+
+    // inserted pages match the size of the document's first page
+    let blank_box = match originals.values().next() {
+        Some(id) => media_box(&doc, *id)?,
+        None => [0.0, 0.0, WIDTH * SCALE, HEIGHT * SCALE],
+    };
+
+    let mut shown = Vec::with_capacity(pages.len());
+    for (redirect, page) in pages {
+        let original = redirect.and_then(|i| originals.get(&(i + 1)).copied());
+
+        let (mut dict, media_box) = match original {
+            Some(id) => (flatten_page(&doc, id)?, media_box(&doc, id)?),
+            None => (
+                dictionary! {
+                    "Type" => "Page",
+                    "MediaBox" => blank_box.map(Object::from).to_vec(),
+                    "Resources" => dictionary! {},
+                },
+                blank_box,
+            ),
+        };
+
+        let (content, opacities) = page_content(page, fit_transform(media_box));
+        if !content.operations.is_empty() {
+            add_overlay(&mut doc, &mut dict, content, &opacities)?;
+        }
+        dict.set("Parent", pages_id);
+
+        shown.push((original, dict));
+    }
+
+    // pages are only replaced once every original has been read, and a page shown twice
+    // needs its own object for each
+    let mut used = HashSet::new();
+    let kids: Vec<Object> = shown
+        .into_iter()
+        .map(|(original, dict)| match original {
+            Some(id) if used.insert(id) => {
+                doc.objects.insert(id, Object::Dictionary(dict));
+                id.into()
+            }
+            _ => doc.add_object(dict).into(),
+        })
+        .collect();
+
+    let root = doc.get_object_mut(pages_id)?.as_dict_mut()?;
+    root.set("Count", kids.len() as i64);
+    root.set("Kids", kids);
+
+    doc.prune_objects();
+    doc.compress();
+
+    let mut out = Vec::new();
+    doc.save_to(&mut out)?;
+
+    Ok(out)
+}
+
+/// Maps pixels of the tablet's canvas onto a page of the tablet's size.
+const NATIVE_TRANSFORM: [f32; 6] = [SCALE, 0.0, 0.0, -SCALE, 0.0, HEIGHT * SCALE];
+
+/// The number of pages in a PDF.
+pub fn page_count(pdf: &[u8]) -> eyre::Result<u32> {
+    Ok(Document::load_mem(pdf)?.get_pages().len() as u32)
+}
+
+/// Maps pixels of the tablet's canvas onto a page with `media_box`, scaled to fit the screen
+/// and centered horizontally like the tablet displays it.
+fn fit_transform([x0, y0, x1, y1]: [f32; 4]) -> [f32; 6] {
+    let (width, height) = (x1 - x0, y1 - y0);
+    let scale = (width / WIDTH).max(height / HEIGHT);
+
+    [
+        scale,
+        0.0,
+        0.0,
+        -scale,
+        x0 + width / 2.0 - WIDTH / 2.0 * scale,
+        y1,
+    ]
+}
+
+/// The drawing operations for a page, mapped from canvas pixels by `transform`, and the
+/// opacities (in percent) they use. Pages without strokes have no operations.
+fn page_content(page: &Page, transform: [f32; 6]) -> (Content, BTreeSet<u8>) {
+    let mut operations = Vec::new();
+    let mut opacities = BTreeSet::new();
+
+    for stroke in page.visible_lines().flat_map(brush::strokes) {
+        write_stroke(&mut operations, &mut opacities, &stroke);
+    }
+
+    if !operations.is_empty() {
+        operations.splice(
+            0..0,
+            [
+                Operation::new("q", vec![]),
+                Operation::new("cm", transform.map(Object::from).to_vec()),
+                Operation::new("j", vec![1.into()]),
+            ],
+        );
+        operations.push(Operation::new("Q", vec![]));
+    }
+
+    (Content { operations }, opacities)
+}
+
+/// A copy of a page's dictionary, with everything it inherits from its ancestors copied in
+/// so it can be moved elsewhere in the page tree.
+fn flatten_page(doc: &Document, id: ObjectId) -> eyre::Result<Dictionary> {
+    let mut dict = doc.get_dictionary(id)?.clone();
+
+    for key in [&b"Resources"[..], b"MediaBox", b"CropBox", b"Rotate"] {
+        if dict.has(key) {
+            continue;
+        }
+        if let Some(value) = inherited(doc, id, key)? {
+            dict.set(key, value);
+        }
+    }
+
+    Ok(dict)
+}
+
+/// Look up an attribute a page may inherit from the page tree above it.
+fn inherited(doc: &Document, mut id: ObjectId, key: &[u8]) -> eyre::Result<Option<Object>> {
+    // guard against loops in broken files
+    for _ in 0..64 {
+        let dict = doc.get_dictionary(id)?;
+        if let Ok(value) = dict.get(key) {
+            return Ok(Some(value.clone()));
+        }
+
+        match dict.get(b"Parent").and_then(Object::as_reference) {
+            Ok(parent) => id = parent,
+            Err(_) => break,
+        }
+    }
+
+    Ok(None)
+}
+
+fn media_box(doc: &Document, page: ObjectId) -> eyre::Result<[f32; 4]> {
+    let Some(rect) = inherited(doc, page, b"MediaBox")? else {
+        return Err(eyre::eyre!("page {page:?} has no MediaBox"));
+    };
+
+    let rect = deref(doc, &rect)?;
+    let numbers = rect
+        .as_array()?
+        .iter()
+        .map(|n| deref(doc, n)?.as_float().map_err(eyre::Error::from))
+        .collect::<eyre::Result<Vec<f32>>>()?;
+
+    match numbers[..] {
+        [a, b, c, d] => Ok([a.min(c), b.min(d), a.max(c), b.max(d)]),
+        _ => Err(eyre::eyre!("invalid MediaBox {numbers:?}")),
+    }
+}
+
+/// Resolve `object` if it's a reference.
+fn deref(doc: &Document, object: &Object) -> eyre::Result<Object> {
+    match object {
+        Object::Reference(id) => Ok(doc.get_object(*id)?.clone()),
+        other => Ok(other.clone()),
+    }
+}
+
+/// Draw `content` on top of the page `dict`, isolated from the page's own graphics state.
+fn add_overlay(
+    doc: &mut Document,
+    dict: &mut Dictionary,
+    content: Content,
+    opacities: &BTreeSet<u8>,
+) -> eyre::Result<()> {
+    let mut contents = match dict.get(b"Contents").map(|c| deref(doc, c)) {
+        Ok(Ok(Object::Array(streams))) => streams,
+        Ok(Ok(_)) => vec![dict.get(b"Contents")?.clone()],
+        Ok(Err(err)) => return Err(err),
+        Err(_) => Vec::new(),
+    };
+
+    let mut after = vec![Operation::new("Q", vec![])];
+    after.extend(content.operations);
+
+    let before = doc.add_object(Stream::new(dictionary! {}, b"q".to_vec()));
+    let after = doc.add_object(Stream::new(
+        dictionary! {},
+        Content { operations: after }.encode()?,
+    ));
+    contents.insert(0, before.into());
+    contents.push(after.into());
+    dict.set("Contents", contents);
+
+    let mut resources = match dict.get(b"Resources") {
+        Ok(resources) => deref(doc, resources)?.as_dict()?.clone(),
+        Err(_) => Dictionary::new(),
+    };
+    let mut states = match resources.get(b"ExtGState") {
+        Ok(states) => deref(doc, states)?.as_dict()?.clone(),
+        Err(_) => Dictionary::new(),
+    };
+    states.extend(&graphics_states(opacities));
+    resources.set("ExtGState", states);
+    dict.set("Resources", resources);
+
+    Ok(())
+}
+
+fn write_stroke(operations: &mut Vec<Operation>, opacities: &mut BTreeSet<u8>, stroke: &Stroke) {
+    let [r, g, b] = stroke.color.map(|c| f32::from(c) / 255.0);
+    let cap = match stroke.cap {
+        Cap::Round => 1,
+        Cap::Square => 2,
+    };
+
+    operations.push(Operation::new("q", vec![]));
+
+    let opacity = (stroke.opacity * 100.0).round() as u8;
+    if opacity < 100 {
+        opacities.insert(opacity);
+        operations.push(Operation::new(
+            "gs",
+            vec![Object::Name(graphics_state(opacity).into_bytes())],
+        ));
+    }
+
+    operations.push(Operation::new("RG", vec![r.into(), g.into(), b.into()]));
+    operations.push(Operation::new("w", vec![stroke.width.into()]));
+    operations.push(Operation::new("J", vec![cap.into()]));
+
+    for (i, (x, y)) in stroke.points.iter().enumerate() {
+        let operator = match i {
+            0 => "m",
+            _ => "l",
+        };
+        operations.push(Operation::new(operator, vec![(*x).into(), (*y).into()]));
+    }
+
+    operations.push(Operation::new("S", vec![]));
+    operations.push(Operation::new("Q", vec![]));
+}
+
+/// The name of the graphics state stroking with `opacity` percent, unlikely to clash with the
+/// names in an existing PDF.
+fn graphics_state(opacity: u8) -> String {
+    format!("RmGS{opacity}")
+}
+
+fn graphics_states(opacities: &BTreeSet<u8>) -> Dictionary {
+    opacities
+        .iter()
+        .map(|opacity| {
+            (
+                graphics_state(*opacity),
+                Object::Dictionary(dictionary! {
+                    "Type" => "ExtGState",
+                    "CA" => f32::from(*opacity) / 100.0,
+                }),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remarkable::lines::{CrdtId, Layer, Line, Pen, PenColor, Point};
+
+    fn scribble() -> Page {
+        let line = Line {
+            tool: Pen::Highlighter2,
+            color: PenColor::Yellow,
+            thickness_scale: 1.0,
+            starting_length: 0.0,
+            points: vec![
+                Point::from_floats(0.0, 100.0, 0.0, 0.0, 20.0, 1.0),
+                Point::from_floats(100.0, 100.0, 0.0, 0.0, 20.0, 1.0),
+            ],
+            timestamp: CrdtId::default(),
+            move_id: None,
+        };
+
+        Page {
+            layers: vec![Layer {
+                id: CrdtId::new(0, 11),
+                name: "Layer 1".into(),
+                visible: true,
+                lines: vec![line],
+                glyphs: Vec::new(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn contents(doc: &Document, page: ObjectId) -> Vec<u8> {
+        doc.get_page_content(page).unwrap()
+    }
+
+    #[test]
+    fn renders_one_page_per_page() {
+        let pages = vec![Page::default(); 3];
+        let bytes = render(&pages).unwrap();
+
+        let doc = Document::load_mem(&bytes).unwrap();
+        assert_eq!(doc.get_pages().len(), 3);
+        assert_eq!(page_count(&bytes).unwrap(), 3);
+    }
+
+    #[test]
+    fn annotates_original_pages() {
+        let original = render(&[Page::default(), Page::default()]).unwrap();
+
+        let pages = [
+            (Some(1), scribble()),
+            (None, Page::default()),
+            (Some(1), Page::default()),
+        ];
+        let annotated = annotate(&original, &pages).unwrap();
+
+        let doc = Document::load_mem(&annotated).unwrap();
+        let ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        assert_eq!(ids.len(), 3);
+
+        // the second original page is shown twice, and the first not at all
+        assert_ne!(ids[0], ids[2]);
+        for id in &ids {
+            assert_eq!(
+                media_box(&doc, *id).unwrap(),
+                [0.0, 0.0, WIDTH * SCALE, HEIGHT * SCALE]
+            );
+        }
+
+        let scribbled = String::from_utf8_lossy(&contents(&doc, ids[0])).into_owned();
+        assert!(scribbled.contains("RmGS30 gs"), "{scribbled}");
+        assert!(!String::from_utf8_lossy(&contents(&doc, ids[2])).contains("gs"));
+    }
+
+    #[test]
+    fn fits_pages_to_the_screen() {
+        // a US letter page is wider than the screen, so its width is what fits
+        let [a, _, _, d, e, f] = fit_transform([0.0, 0.0, 612.0, 792.0]);
+        assert_eq!(a, 612.0 / WIDTH);
+        assert_eq!(d, -a);
+        assert_eq!((e, f), (0.0, 792.0));
+    }
+}
@@ -0,0 +1,179 @@
+//! Anti-aliased PNG output, one image per page.
+
+use color_eyre::eyre;
+use tiny_skia::{LineCap, LineJoin, Paint, PathBuilder, Pixmap, Transform};
+
+use crate::remarkable::lines::Page;
+
+use super::{
+    brush::{self, Cap, Stroke},
+    HEIGHT, WIDTH,
+};
+
+pub const CONTENT_TYPE: &str = "image/png";
+
+/// The resolution of the tablet's screen, which pages are drawn at by default.
+pub const NATIVE_DPI: f32 = 227.0;
+
+/// Scale factor of the thumbnails the tablet shows in its file browser.
+pub const THUMBNAIL_SCALE: f32 = 0.2;
+
+/// The largest scale pages are drawn at, about 900 dpi. The image is held in memory while it's
+/// drawn, 170 MB at this scale, which the tablet can still spare.
+pub const MAX_SCALE: f32 = 4.0;
+
+/// Render a page as a PNG image, `scale` times the size of the tablet's screen.
+pub fn render(page: &Page, scale: f32) -> eyre::Result<Vec<u8>> {
+    Ok(rasterize(page, scale)?.encode_png()?)
+}
+
+/// The scale needed to render a page at `dpi`.
+pub fn scale_for_dpi(dpi: f32) -> f32 {
+    dpi / NATIVE_DPI
+}
+
+/// Draw a page onto a white image.
+pub fn rasterize(page: &Page, scale: f32) -> eyre::Result<Pixmap> {
+    if !(scale.is_finite() && scale > 0.0 && scale <= MAX_SCALE) {
+        return Err(eyre::eyre!("invalid scale {scale}"));
+    }
+
+    let width = (WIDTH * scale) as u32;
+    let height = (HEIGHT * scale) as u32;
+    let Some(mut pixmap) = Pixmap::new(width, height) else {
+        return Err(eyre::eyre!("can't render a page at {width}x{height}"));
+    };
+    pixmap.fill(tiny_skia::Color::WHITE);
+
+    let transform = Transform::from_scale(scale, scale);
+    for stroke in page.visible_lines().flat_map(brush::strokes) {
+        draw_stroke(&mut pixmap, &stroke, transform);
+    }
+
+    Ok(pixmap)
+}
+
+fn draw_stroke(pixmap: &mut Pixmap, stroke: &Stroke, transform: Transform) {
+    let mut path = PathBuilder::new();
+    for (i, (x, y)) in stroke.points.iter().enumerate() {
+        match i {
+            0 => path.move_to(*x, *y),
+            _ => path.line_to(*x, *y),
+        }
+    }
+    // only empty or non-finite paths are rejected, and those have nothing to draw anyway
+    let Some(path) = path.finish() else {
+        return;
+    };
+
+    let [r, g, b] = stroke.color;
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(r, g, b, (stroke.opacity * 255.0).round() as u8);
+    paint.anti_alias = true;
+
+    let style = tiny_skia::Stroke {
+        width: stroke.width,
+        line_cap: match stroke.cap {
+            Cap::Round => LineCap::Round,
+            Cap::Square => LineCap::Square,
+        },
+        line_join: LineJoin::Round,
+        ..Default::default()
+    };
+
+    pixmap.stroke_path(&path, &paint, &style, transform, None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remarkable::lines;
+
+    const SAMPLE_PAGE: &[u8] = include_bytes!(
+        "../../samples/v6/1dc81a48-ecf8-4c11-a3e4-65dda27270a3/2e9c7c50-6699-4686-8b53-c63e5b0d3cee.rm"
+    );
+    const SAMPLE_THUMBNAIL: &[u8] = include_bytes!(
+        "../../samples/v6/1dc81a48-ecf8-4c11-a3e4-65dda27270a3.thumbnails/2e9c7c50-6699-4686-8b53-c63e5b0d3cee.png"
+    );
+
+    fn luminance(pixmap: &Pixmap) -> Vec<f32> {
+        pixmap
+            .pixels()
+            .iter()
+            .map(|p| {
+                let p = p.demultiply();
+                (0.299 * f32::from(p.red())
+                    + 0.587 * f32::from(p.green())
+                    + 0.114 * f32::from(p.blue()))
+                    / 255.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scales_with_dpi() {
+        let page = Page::default();
+
+        assert_eq!(rasterize(&page, 1.0).unwrap().width(), 1404);
+        let pixmap = rasterize(&page, scale_for_dpi(NATIVE_DPI / 2.0)).unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (702, 936));
+        assert!(rasterize(&page, 0.0).is_err());
+        assert!(rasterize(&page, MAX_SCALE).is_ok());
+        assert!(rasterize(&page, 50.0).is_err());
+        assert!(rasterize(&page, f32::INFINITY).is_err());
+    }
+
+    #[test]
+    fn encodes_png() {
+        let png = render(&Page::default(), THUMBNAIL_SCALE).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+
+    /// Compare against the thumbnail the tablet drew of the same page.
+    #[test]
+    fn matches_device_thumbnail() {
+        let page = lines::read(SAMPLE_PAGE).unwrap();
+        let ours = rasterize(&page, THUMBNAIL_SCALE).unwrap();
+        let theirs = Pixmap::decode_png(SAMPLE_THUMBNAIL).unwrap();
+
+        assert_eq!(
+            (ours.width(), ours.height()),
+            (theirs.width(), theirs.height())
+        );
+
+        let width = ours.width() as usize;
+        let (ours, theirs) = (luminance(&ours), luminance(&theirs));
+
+        // the template's grid is lighter than any stroke, so it isn't counted as ink
+        let ink = |image: &[f32], i: usize| image[i] < 0.6;
+        // whether there's ink within a pixel of `i`, to allow for slightly different edges
+        let ink_near = |image: &[f32], i: usize| {
+            let (x, y) = ((i % width) as isize, (i / width) as isize);
+            (-1..=1).any(|dy| {
+                (-1..=1).any(|dx| {
+                    let (x, y) = (x + dx, y + dy);
+                    (0..width as isize).contains(&x)
+                        && (0..(image.len() / width) as isize).contains(&y)
+                        && ink(image, y as usize * width + x as usize)
+                })
+            })
+        };
+        let matching = |a: &[f32], b: &[f32]| {
+            let total = (0..a.len()).filter(|i| ink(a, *i)).count();
+            let matched = (0..a.len())
+                .filter(|i| ink(a, *i) && ink_near(b, *i))
+                .count();
+            matched as f32 / total as f32
+        };
+
+        // typed text isn't drawn yet, so some of the device's ink is expected to be missing
+        let recall = matching(&theirs, &ours);
+        let precision = matching(&ours, &theirs);
+
+        assert!(recall > 0.8, "only drew {recall} of the device's ink");
+        assert!(
+            precision > 0.8,
+            "only {precision} of our ink was drawn by the device"
+        );
+    }
+}
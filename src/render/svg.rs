@@ -0,0 +1,71 @@
+//! SVG output, one document per page.
+
+use std::fmt::Write;
+
+use crate::remarkable::lines::Page;
+
+use super::{
+    brush::{self, Cap, Stroke},
+    HEIGHT, WIDTH,
+};
+
+pub const CONTENT_TYPE: &str = "image/svg+xml";
+
+/// Render a page as an SVG document at the tablet's native resolution.
+pub fn render(page: &Page) -> String {
+    let mut svg = String::new();
+
+    // writing to a String can't fail
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}">"#
+    );
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+
+    for layer in page.layers.iter().filter(|l| l.visible) {
+        let _ = writeln!(svg, r#"<g data-layer="{}">"#, escape(&layer.name));
+
+        for stroke in layer.lines.iter().flat_map(brush::strokes) {
+            write_stroke(&mut svg, &stroke);
+        }
+
+        let _ = writeln!(svg, "</g>");
+    }
+
+    let _ = writeln!(svg, "</svg>");
+
+    svg
+}
+
+fn write_stroke(svg: &mut String, stroke: &Stroke) {
+    let [r, g, b] = stroke.color;
+    let cap = match stroke.cap {
+        Cap::Round => "round",
+        Cap::Square => "square",
+    };
+
+    let _ = write!(
+        svg,
+        r#"<polyline fill="none" stroke="rgb({r},{g},{b})" stroke-width="{:.2}" stroke-linecap="{cap}" stroke-linejoin="round""#,
+        stroke.width
+    );
+
+    if stroke.opacity < 1.0 {
+        let _ = write!(svg, r#" stroke-opacity="{:.2}""#, stroke.opacity);
+    }
+
+    let _ = write!(svg, r#" points=""#);
+    for (x, y) in &stroke.points {
+        let _ = write!(svg, "{x:.2},{y:.2} ");
+    }
+
+    let _ = writeln!(svg, r#""/>"#);
+}
+
+/// Escape text for use in an attribute value.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -2,17 +2,19 @@ use std::{path::PathBuf, str::FromStr, sync::Arc};
 
 use axum::{
     extract::{Query, State},
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     routing, Router,
 };
 use maud::{html, Markup, PreEscaped, DOCTYPE};
 
-use crate::remarkable::Remarkable;
+use crate::{remarkable::Remarkable, render::png};
 
 pub fn router() -> Router<Arc<Remarkable>> {
     Router::new()
         .route("/", routing::get(root))
         .route("/explorer", routing::get(explorer))
+        .route("/preview", routing::get(preview))
         .fallback(routing::get(fallback))
 }
 
@@ -21,7 +23,7 @@ async fn root(state: State<Arc<Remarkable>>) -> Response {
         "rm-cloudsync",
         html! {
             h1 { "rm-cloudsync" }
-            (explorer(Query(ExplorerQuery { path: PathBuf::from_str("/Trash").unwrap() }), state).await)
+            (explorer(Query(ExplorerQuery { path: PathBuf::from_str("/").unwrap() }), state).await)
         },
     ).into_response()
 }
@@ -32,23 +34,84 @@ struct ExplorerQuery {
 }
 
 async fn explorer(Query(query): Query<ExplorerQuery>, State(fs): State<Arc<Remarkable>>) -> Markup {
-    // let elems = match fs.list(&query.path).await {
-    //     Ok(elems) => elems,
-    //     Err(err) => {
-    //         return html! {
-    //             "Error: " (format!("{err:#?}"))
-    //         };
-    //     }
-    // };
+    let mut elems = match fs.list(&query.path).await {
+        Ok(elems) => elems,
+        Err(err) => {
+            return html! {
+                "Error: " (format!("{err:#?}"))
+            };
+        }
+    };
 
-    let elems = fs.pinned().await;
+    // most recently used first
+    elems.sort_by_key(|(_, e, _)| std::cmp::Reverse(e.last_used()));
 
     html! {
         #explorer {
             p { "path: " (format!("{:?}", query.path)) }
-            (format!("{elems:?}"))
+            ul {
+                @for (_, elem, name) in elems {
+                    @let path = query.path.join(&name);
+                    li {
+                        @if elem.is_file() {
+                            img src=(format!("/preview?path={}", encode(&path))) alt=(name) loading="lazy";
+                        }
+                        (name)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A thumbnail of the first page of the document at `path`.
+async fn preview(Query(query): Query<ExplorerQuery>, State(fs): State<Arc<Remarkable>>) -> Response {
+    let rendered = match fs.read_page(&query.path, 0).await {
+        Ok(page) => png::render(&page, png::THUMBNAIL_SCALE),
+        Err(err) => Err(err),
+    };
+
+    match rendered {
+        Ok(png) => ([(header::CONTENT_TYPE, png::CONTENT_TYPE)], png).into_response(),
+        Err(err) => {
+            tracing::debug!("no preview for {:?}: {err}", query.path);
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+/// Percent-encode a path for use in a URL or query string.
+pub fn encode(path: &std::path::Path) -> String {
+    path.to_string_lossy()
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                char::from(b).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Decode a percent-encoded path, the reverse of [`encode`].
+pub fn decode(path: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+
+        if b != b'%' {
+            bytes.push(b);
+            continue;
         }
+
+        let hex = tail.get(..2).filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))?;
+        bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+        rest = &tail[2..];
     }
+
+    String::from_utf8(bytes).ok()
 }
 
 async fn fallback() -> Markup {